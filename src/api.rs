@@ -0,0 +1,214 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use anyhow::Error;
+use axum::{
+    extract::{Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use chrono::NaiveTime;
+use poise::serenity_prelude::GuildId;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data::{GuildData, SubjectInfo, TaskFilter},
+    ics::{self, IcsOptions},
+    Data, Task,
+};
+
+/// 外部から見たHTTP APIのURL。HTTP APIが無効の場合はNoneを返します。
+pub fn public_url() -> Option<String> {
+    let addr = std::env::var("HTTP_API_ADDR").ok()?;
+    Some(
+        std::env::var("HTTP_PUBLIC_URL")
+            .map(|url| url.trim_end_matches('/').to_string())
+            .unwrap_or_else(|_| format!("http://{}", addr)),
+    )
+}
+
+/// 読み取り専用のHTTP APIを起動します。
+/// 購読用カレンダーのために公開されることがあるので、JSON APIは`HTTP_API_TOKEN`を設定した場合のみ有効にし、
+/// `Authorization: Bearer <トークン>`を必須にします。
+pub async fn serve(data: Data, addr: SocketAddr) -> Result<(), Error> {
+    let mut app = Router::new().route("/calendar.ics", get(calendar));
+    match std::env::var("HTTP_API_TOKEN")
+        .ok()
+        .filter(|t| !t.is_empty())
+    {
+        Some(token) => {
+            let json_api = Router::new()
+                .route("/tasks", get(tasks))
+                .route("/subjects", get(subjects))
+                .route("/suggest_times", get(suggest_times))
+                .route_layer(middleware::from_fn_with_state(
+                    Arc::new(token),
+                    require_token,
+                ));
+            app = app.merge(json_api);
+        }
+        // 以前はHTTP_API_ADDRだけでJSON APIが有効だったので、設定を見直してもらえるよう警告する
+        None => eprintln!(
+            "Warning: HTTP_API_TOKEN is not set, so /tasks, /subjects and /suggest_times are disabled. \
+             The JSON API now requires a bearer token; set HTTP_API_TOKEN to enable it again."
+        ),
+    }
+    let app = app.with_state(data);
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    println!("HTTP API listening on {}", addr);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct GuildQuery {
+    guild: Option<GuildId>,
+}
+
+#[derive(Deserialize)]
+struct TasksQuery {
+    guild: Option<GuildId>,
+    subject: Option<String>,
+    category: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CalendarQuery {
+    token: String,
+}
+
+#[derive(Serialize)]
+struct SuggestTime {
+    time: NaiveTime,
+    label: String,
+}
+
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = serde_json::json!({ "error": self.1 });
+        (self.0, Json(body)).into_response()
+    }
+}
+
+impl From<Error> for ApiError {
+    fn from(error: Error) -> Self {
+        ApiError(StatusCode::BAD_REQUEST, error.to_string())
+    }
+}
+
+async fn require_token(
+    State(token): State<Arc<String>>,
+    headers: HeaderMap,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let authorized = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| constant_time_eq(given.as_bytes(), token.as_bytes()));
+    if !authorized {
+        return Err(ApiError(StatusCode::UNAUTHORIZED, "Invalid token".into()));
+    }
+    Ok(next.run(request).await)
+}
+
+// トークンを比べるのにかかる時間から、一致している部分を推測されないようにする
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// ギルドが1つだけの場合は、`guild`を省略できる
+fn guild(data: &Data, guild_id: Option<GuildId>) -> Result<Arc<GuildData>, ApiError> {
+    let guilds = data.guilds.lock().unwrap();
+    match guild_id {
+        Some(guild_id) => guilds.get(&guild_id).cloned().ok_or(ApiError(
+            StatusCode::NOT_FOUND,
+            format!("Unknown guild: {}", guild_id),
+        )),
+        None if guilds.len() == 1 => Ok(guilds.values().next().unwrap().clone()),
+        None => Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            "Specify guild in the query".into(),
+        )),
+    }
+}
+
+async fn tasks(
+    State(data): State<Data>,
+    Query(query): Query<TasksQuery>,
+) -> Result<Json<Vec<Task>>, ApiError> {
+    let guild_data = guild(&data, query.guild)?;
+    let filter = TaskFilter::parse(query.subject, query.category, query.from, query.to)?;
+
+    Ok(Json(guild_data.filtered_tasks(&filter)))
+}
+
+async fn subjects(
+    State(data): State<Data>,
+    Query(query): Query<GuildQuery>,
+) -> Result<Json<Vec<SubjectInfo>>, ApiError> {
+    let guild_data = guild(&data, query.guild)?;
+    let subjects = guild_data.subjects.lock().unwrap().clone();
+    Ok(Json(subjects))
+}
+
+async fn suggest_times(
+    State(data): State<Data>,
+    Query(query): Query<GuildQuery>,
+) -> Result<Json<Vec<SuggestTime>>, ApiError> {
+    let guild_data = guild(&data, query.guild)?;
+    let suggest_times = guild_data
+        .suggest_times
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(time, label)| SuggestTime {
+            time: *time,
+            label: label.clone(),
+        })
+        .collect();
+    Ok(Json(suggest_times))
+}
+
+async fn calendar(
+    State(data): State<Data>,
+    Query(query): Query<CalendarQuery>,
+) -> Result<Response, ApiError> {
+    let guilds = data.guilds.lock().unwrap().clone();
+    let (guild_data, feed) = guilds
+        .values()
+        .find_map(|guild_data| {
+            let feed = guild_data
+                .calendar_feeds
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|f| constant_time_eq(f.token.as_bytes(), query.token.as_bytes()))
+                .cloned()?;
+            Some((guild_data, feed))
+        })
+        .ok_or(ApiError(StatusCode::NOT_FOUND, "Unknown token".into()))?;
+
+    let tasks = guild_data.filtered_tasks(&feed.filter());
+    let calendar = ics::render(
+        &tasks,
+        &IcsOptions {
+            name: feed.name,
+            reminder: None,
+        },
+    );
+
+    Ok((
+        [(header::CONTENT_TYPE, "text/calendar; charset=utf-8")],
+        calendar,
+    )
+        .into_response())
+}
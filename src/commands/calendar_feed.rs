@@ -0,0 +1,185 @@
+use std::time::Duration;
+
+use anyhow::{Context as _, Error};
+use chrono::Local;
+use futures::StreamExt;
+use poise::serenity_prelude::*;
+
+use crate::{
+    api,
+    data::{self, CalendarFeed, TaskFilter},
+    utilities::{autocomplete_category, autocomplete_subject, format_datetime},
+    PoiseContext,
+};
+
+#[poise::command(slash_command, guild_only, ephemeral)]
+/// カレンダーアプリで購読できるURLを発行します。
+pub async fn create_calendar_feed(
+    ctx: PoiseContext<'_>,
+    #[description = "教科で絞り込む"]
+    #[autocomplete = "autocomplete_subject"]
+    subject: Option<String>,
+    #[description = "カテゴリーで絞り込む"]
+    #[autocomplete = "autocomplete_category"]
+    category: Option<String>,
+) -> Result<(), Error> {
+    let (guild_id, guild_data) = data::of(ctx)?;
+    let base_url = api::public_url().context("HTTP APIが有効になっていません")?;
+    let filter = TaskFilter::parse(subject, category, None, None)?;
+
+    let mut feed = CalendarFeed {
+        token: uuid::Uuid::new_v4().simple().to_string(),
+        name: String::new(),
+        subject: filter.subject,
+        category: filter.category,
+        created_by: ctx.author().id,
+        created_at: Local::now(),
+    };
+    feed.name = format!(
+        "{} ({})",
+        ctx.guild()
+            .map(|g| g.name.clone())
+            .unwrap_or("タスク".into()),
+        feed.label()
+    );
+    guild_data.calendar_feeds.lock().unwrap().push(feed.clone());
+    ctx.data().request_save(guild_id);
+
+    let url = format!("{}/calendar.ics?token={}", base_url, feed.token);
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("購読用のURLを発行しました")
+                .description(format!(
+                    "カレンダーアプリでこのURLを購読してください\n```\n{}\n```\nURLを知っていれば誰でもタスクを見られるため、共有しないでください",
+                    url
+                ))
+                .field("内容", feed.label(), false)
+                .color(Color::DARK_GREEN),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, ephemeral)]
+/// 購読用のURLを無効にします。
+pub async fn remove_calendar_feed(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    const FEED: &str = "feed";
+    const SUBMIT: &str = "submit";
+
+    let (guild_id, guild_data) = data::of(ctx)?;
+    let is_admin = ctx
+        .author_member()
+        .await
+        .and_then(|m| m.permissions)
+        .is_some_and(|p| p.administrator());
+    let feeds = guild_data
+        .calendar_feeds
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|f| is_admin || f.created_by == ctx.author().id)
+        .cloned()
+        .collect::<Vec<_>>();
+    anyhow::ensure!(!feeds.is_empty(), "無効にできるURLがありません");
+
+    let components = |selected: Option<&str>| {
+        let options = CreateSelectMenuKind::String {
+            options: feeds
+                .iter()
+                .rev()
+                .take(25)
+                .map(|f| {
+                    CreateSelectMenuOption::new(f.label(), f.token.clone())
+                        .description(format!("{}に発行", format_datetime(f.created_at)))
+                        .default_selection(selected == Some(f.token.as_str()))
+                })
+                .collect(),
+        };
+
+        vec![
+            CreateActionRow::SelectMenu(
+                CreateSelectMenu::new(FEED, options).placeholder("無効にするURL"),
+            ),
+            CreateActionRow::Buttons(vec![CreateButton::new(SUBMIT)
+                .style(ButtonStyle::Danger)
+                .label("無効にする")
+                .disabled(selected.is_none())]),
+        ]
+    };
+
+    let mut select: Option<String> = None;
+
+    let message = ctx
+        .send(
+            poise::CreateReply::default()
+                .embed(
+                    CreateEmbed::default()
+                        .title("無効にするURLを選択してください")
+                        .color(Color::DARK_BLUE),
+                )
+                .components(components(None)),
+        )
+        .await?;
+
+    let mut interaction_stream = message
+        .clone()
+        .into_message()
+        .await?
+        .await_component_interaction(ctx)
+        .author_id(ctx.author().id)
+        .timeout(Duration::from_secs(60 * 30))
+        .stream();
+
+    let mut last_interaction = None;
+    while let Some(interaction) = interaction_stream.next().await {
+        match &interaction.data.kind {
+            ComponentInteractionDataKind::StringSelect { values } => {
+                if interaction.data.custom_id == FEED {
+                    select.replace(values[0].clone());
+                }
+                let response = CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::default()
+                        .components(components(select.as_deref())),
+                );
+                interaction.create_response(ctx, response).await?;
+            }
+            ComponentInteractionDataKind::Button if interaction.data.custom_id == SUBMIT => {
+                last_interaction.replace(interaction);
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    let token = select.context("Feed not selected")?;
+    let feed = feeds
+        .iter()
+        .find(|f| f.token == token)
+        .context("Invalid feed")?;
+    guild_data
+        .calendar_feeds
+        .lock()
+        .unwrap()
+        .retain(|f| f.token != token);
+    ctx.data().request_save(guild_id);
+
+    let response = CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::default()
+            .embed(
+                CreateEmbed::default()
+                    .title("URLを無効にしました")
+                    .description(feed.label())
+                    .color(Color::DARK_GREEN),
+            )
+            .components(vec![]),
+    );
+    last_interaction
+        .context("No interaction")?
+        .create_response(ctx, response)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,154 @@
+use anyhow::{Context as _, Error};
+use chrono::{Local, NaiveDate, NaiveTime};
+use poise::serenity_prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    data::{self, Data, GuildData},
+    PoiseContext, Task,
+};
+
+// 通知メッセージの完了ボタンのcustom_idの先頭
+pub const COMPLETE_PREFIX: &str = "complete:";
+
+/// タスク(繰り返す場合はその回)を表す文字列を返します。
+pub fn task_key(task: &Task) -> String {
+    match task.occurrence_date() {
+        Some(date) => format!("{}:{}", task.id, date),
+        None => task.id.to_string(),
+    }
+}
+
+/// `task_key`で作った文字列からタスクを探します。
+pub fn find_task(guild_data: &GuildData, key: &str) -> Option<Task> {
+    let (id, date) = match key.split_once(':') {
+        Some((id, date)) => (id, Some(date.parse::<NaiveDate>().ok()?)),
+        None => (key, None),
+    };
+    let task = guild_data.task(id.parse::<Uuid>().ok()?)?;
+    match date {
+        Some(date) => task.occurrences().find(|t| t.datetime.date_naive() == date),
+        None => Some(task),
+    }
+}
+
+/// 通知メッセージに付ける、完了を切り替えるボタンを返します。
+pub fn complete_buttons(tasks: &[Task]) -> Vec<CreateActionRow> {
+    tasks
+        .iter()
+        .take(25)
+        .map(|task| {
+            CreateButton::new(format!("{}{}", COMPLETE_PREFIX, task_key(task)))
+                .label(format!(
+                    "完了: {}",
+                    task.to_field().0.chars().take(70).collect::<String>()
+                ))
+                .style(ButtonStyle::Secondary)
+        })
+        .collect::<Vec<_>>()
+        .chunks(5)
+        .map(|buttons| CreateActionRow::Buttons(buttons.to_vec()))
+        .collect()
+}
+
+/// 通知メッセージの完了ボタンが押されたときの処理です。
+pub async fn handle_complete_button(
+    ctx: &Context,
+    data: &Data,
+    interaction: &ComponentInteraction,
+) -> Result<(), Error> {
+    let guild_id = interaction.guild_id.context("Not in a guild")?;
+    let key = interaction
+        .data
+        .custom_id
+        .strip_prefix(COMPLETE_PREFIX)
+        .context("Not a complete button")?;
+    let guild_data = data.guild(guild_id);
+
+    let embed = match find_task(&guild_data, key) {
+        Some(task) => {
+            let completed = guild_data.toggle_completion(&task, interaction.user.id);
+            data.request_save(guild_id);
+            CreateEmbed::default()
+                .title(if completed {
+                    "完了にしました"
+                } else {
+                    "未完了に戻しました"
+                })
+                .fields(vec![guild_data.task_field(&task)])
+                .color(if completed {
+                    Color::DARK_GREEN
+                } else {
+                    Color::DARK_GREY
+                })
+        }
+        None => CreateEmbed::default()
+            .title("タスクが見つかりませんでした")
+            .description("削除されたか、日時が変更された可能性があります")
+            .color(Color::DARK_RED),
+    };
+
+    interaction
+        .create_response(
+            ctx,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::default()
+                    .embed(embed)
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    ephemeral,
+    default_member_permissions = "ADMINISTRATOR"
+)]
+/// 今後のタスクを何人が完了したかを表示します。
+pub async fn completion_status(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let (_, guild_data) = data::of(ctx)?;
+
+    let today = Local::now()
+        .with_time(NaiveTime::MIN)
+        .single()
+        .context("Invalid date")?;
+    let tasks = guild_data.tasks_in(today.., None);
+
+    let fields = tasks.iter().take(25).map(|task| {
+        let (name, _, inline) = guild_data.task_field(task);
+        let users = guild_data.completed_users(task);
+        let mut value = format!("✅ {}人が完了", users.len());
+        let mentions = users
+            .iter()
+            .map(|user| user.mention().to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        // フィールドの文字数の上限を超える場合は人数のみ表示する
+        if !mentions.is_empty() && value.len() + mentions.len() < 1000 {
+            value = format!("{}\n{}", value, mentions);
+        }
+        (name, value, inline)
+    });
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("タスクの完了状況")
+                .description(if tasks.is_empty() {
+                    "今後のタスクはありません"
+                } else {
+                    ""
+                })
+                .fields(fields)
+                .footer(CreateEmbedFooter::new(format!("全{}件", tasks.len())))
+                .color(Color::DARK_BLUE),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use std::collections::BTreeMap;
+
+use anyhow::Error;
+use poise::serenity_prelude::*;
+
+use crate::{
+    data::{self, TaskFilter},
+    spreadsheet::{self, Column},
+    utilities::{autocomplete_category, autocomplete_subject},
+    PoiseContext,
+};
+
+#[poise::command(slash_command, guild_only)]
+/// 過去のものも含めたタスクをCSVで書き出します。
+pub async fn export_csv(
+    ctx: PoiseContext<'_>,
+    #[description = "教科で絞り込む"]
+    #[autocomplete = "autocomplete_subject"]
+    subject: Option<String>,
+    #[description = "カテゴリーで絞り込む"]
+    #[autocomplete = "autocomplete_category"]
+    category: Option<String>,
+    #[description = "この日以降のタスクを書き出す(例: 2024/04/01)"] from: Option<String>,
+    #[description = "この日までのタスクを書き出す(例: 2024/07/31)"] to: Option<String>,
+    #[description = "書き出す列をカンマ区切りで指定(既定: カテゴリー,教科,内容,日時,作成者)"]
+    columns: Option<String>,
+) -> Result<(), Error> {
+    let (_, guild_data) = data::of(ctx)?;
+    let filter = TaskFilter::parse(subject, category, from, to)?;
+    let columns = match columns {
+        Some(columns) => Column::parse_list(&columns)?,
+        None => Column::VALUES.to_vec(),
+    };
+    anyhow::ensure!(!columns.is_empty(), "列を指定してください");
+
+    let tasks = guild_data.filtered_tasks(&filter);
+
+    let mut creators = BTreeMap::new();
+    if columns.contains(&Column::Creator) {
+        for task in &tasks {
+            let Some(user_id) = guild_data.creator(task.id) else {
+                continue;
+            };
+            if creators.contains_key(&user_id) {
+                continue;
+            }
+            let name = match user_id.to_user(ctx).await {
+                Ok(user) => user.global_name.unwrap_or(user.name),
+                Err(_) => user_id.to_string(),
+            };
+            creators.insert(user_id, name);
+        }
+    }
+
+    let csv = spreadsheet::render(&tasks, &columns, |task| {
+        guild_data
+            .creator(task.id)
+            .and_then(|user_id| creators.get(&user_id).cloned())
+            .unwrap_or_default()
+    })?;
+
+    ctx.send(
+        poise::CreateReply::default()
+            .embed(
+                CreateEmbed::default()
+                    .title("タスクを書き出しました")
+                    .description(format!("{}件のタスク", tasks.len()))
+                    .color(Color::DARK_GREEN),
+            )
+            .attachment(CreateAttachment::bytes(csv, "tasks.csv")),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use anyhow::Error;
+use chrono::Duration;
+use poise::serenity_prelude::*;
+
+use crate::{
+    data::{self, TaskFilter},
+    ics::{self, IcsOptions},
+    utilities::{autocomplete_category, autocomplete_subject},
+    PoiseContext,
+};
+
+#[poise::command(slash_command, guild_only)]
+/// タスクをカレンダーアプリに取り込める形式(.ics)で書き出します。
+pub async fn export_ics(
+    ctx: PoiseContext<'_>,
+    #[description = "教科で絞り込む"]
+    #[autocomplete = "autocomplete_subject"]
+    subject: Option<String>,
+    #[description = "カテゴリーで絞り込む"]
+    #[autocomplete = "autocomplete_category"]
+    category: Option<String>,
+    #[description = "この日以降のタスクを書き出す(例: 2024/04/01)"] from: Option<String>,
+    #[description = "この日までのタスクを書き出す(例: 2024/07/31)"] to: Option<String>,
+    #[description = "何分前に通知するか / 0で通知なし(既定: 1日前)"] reminder_minutes: Option<u32>,
+) -> Result<(), Error> {
+    let (_, guild_data) = data::of(ctx)?;
+    let filter = TaskFilter::parse(subject, category, from, to)?;
+
+    let tasks = guild_data.filtered_tasks(&filter);
+
+    let options = IcsOptions {
+        name: ctx
+            .guild()
+            .map(|g| g.name.clone())
+            .unwrap_or("タスク".into()),
+        reminder: match reminder_minutes.unwrap_or(60 * 24) {
+            0 => None,
+            minutes => Some(Duration::minutes(minutes as i64)),
+        },
+    };
+    let calendar = ics::render(&tasks, &options);
+
+    ctx.send(
+        poise::CreateReply::default()
+            .embed(
+                CreateEmbed::default()
+                    .title("タスクを書き出しました")
+                    .description(format!("{}件のタスク", tasks.len()))
+                    .color(Color::DARK_GREEN),
+            )
+            .attachment(CreateAttachment::bytes(calendar.into_bytes(), "tasks.ics")),
+    )
+    .await?;
+
+    Ok(())
+}
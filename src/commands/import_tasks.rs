@@ -0,0 +1,390 @@
+use std::{collections::BTreeSet, time::Duration};
+
+use anyhow::{Context as _, Error};
+use chrono::{DateTime, Local, NaiveDateTime, NaiveTime, TimeZone};
+use poise::serenity_prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    data::{self, GuildData},
+    ics::{self, IcsDateTime, IcsEvent},
+    spreadsheet::{self, CsvRow},
+    utilities::parse_date,
+    PoiseContext, Subject, Task,
+};
+
+// プレビューの各項目に表示する最大の行数
+const MAX_PREVIEW_LINES: usize = 10;
+const MAX_LINE_CHARS: usize = 80;
+
+/// 読み込んだファイルを追加する前に確認するための分類
+#[derive(Default)]
+struct Preview {
+    new: Vec<Task>,
+    duplicates: Vec<Task>,
+    errors: Vec<String>,
+    unknown_subjects: BTreeSet<String>,
+}
+
+#[poise::command(slash_command, guild_only)]
+/// カレンダー(.ics)やCSVファイルからタスクを読み込みます。
+pub async fn import_tasks(
+    ctx: PoiseContext<'_>,
+    #[description = "読み込むファイル(.ics / .csv)"] file: Attachment,
+) -> Result<(), Error> {
+    const CONFIRM: &str = "confirm";
+    const CANCEL: &str = "cancel";
+
+    let (guild_id, guild_data) = data::of(ctx)?;
+
+    let bytes = file.download().await?;
+    let filename = file.filename.to_lowercase();
+    let rows = if filename.ends_with(".ics") {
+        let text = String::from_utf8(bytes).context("ファイルをUTF-8として読み取れませんでした")?;
+        ics::parse(&text)?
+            .into_iter()
+            .enumerate()
+            .map(|(i, event)| {
+                from_event(&guild_data, &event).map_err(|e| format!("{}件目の予定: {}", i + 1, e))
+            })
+            .collect::<Vec<_>>()
+    } else if filename.ends_with(".csv") {
+        spreadsheet::parse(&bytes)?
+            .into_iter()
+            .map(|row| from_row(&guild_data, &row).map_err(|e| format!("{}行目: {}", row.line, e)))
+            .collect::<Vec<_>>()
+    } else {
+        anyhow::bail!(".icsまたは.csvファイルを指定してください");
+    };
+
+    let mut preview = Preview::default();
+    // ファイルの中での重複
+    let mut seen_ids = BTreeSet::new();
+    let mut seen_keys = BTreeSet::new();
+    for row in rows {
+        match row {
+            Ok((task, unknown_subject)) => {
+                preview.unknown_subjects.extend(unknown_subject);
+                let new_id = seen_ids.insert(task.id);
+                let new_key =
+                    seen_keys.insert((task.subject.clone(), task.details.clone(), task.datetime));
+                if guild_data.has_duplicate(&task) || !new_id || !new_key {
+                    preview.duplicates.push(task);
+                } else {
+                    preview.new.push(task);
+                }
+            }
+            Err(e) => preview.errors.push(e),
+        }
+    }
+
+    let message = ctx
+        .send(
+            poise::CreateReply::default()
+                .embed(
+                    CreateEmbed::default()
+                        .title(format!("{}件のタスクを追加しますか？", preview.new.len()))
+                        .description(file.filename.clone())
+                        .fields(preview_fields(&preview))
+                        .color(Color::DARK_BLUE),
+                )
+                .components(vec![CreateActionRow::Buttons(vec![
+                    CreateButton::new(CONFIRM)
+                        .label("追加する")
+                        .style(ButtonStyle::Primary)
+                        .disabled(preview.new.is_empty()),
+                    CreateButton::new(CANCEL)
+                        .label("キャンセル")
+                        .style(ButtonStyle::Secondary),
+                ])]),
+        )
+        .await?;
+
+    let interaction = message
+        .into_message()
+        .await?
+        .await_component_interaction(ctx)
+        .author_id(ctx.author().id)
+        .timeout(Duration::from_secs(60 * 30))
+        .await
+        .context("No interaction")?;
+
+    if interaction.data.custom_id != CONFIRM {
+        let response = CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::default()
+                .embed(
+                    CreateEmbed::default()
+                        .title("読み込みをキャンセルしました")
+                        .color(Color::DARK_GREY),
+                )
+                .components(vec![]),
+        );
+        interaction.create_response(ctx, response).await?;
+        return Ok(());
+    }
+
+    // 確認している間に追加されたものは飛ばす
+    // 1回の読み込みは/undoでまとめて取り消せるように、同じbatchで追加する
+    let tasks = preview
+        .new
+        .into_iter()
+        .filter(|task| !guild_data.has_duplicate(task))
+        .collect::<Vec<_>>();
+    let added = guild_data.add_tasks(ctx.author().id, tasks).len();
+    ctx.data().request_save(guild_id);
+
+    let response = CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::default()
+            .embed(
+                CreateEmbed::default()
+                    .title(format!("{}件のタスクを追加しました", added))
+                    .description(file.filename)
+                    .color(Color::DARK_GREEN),
+            )
+            .components(vec![]),
+    );
+    interaction.create_response(ctx, response).await?;
+
+    Ok(())
+}
+
+/// iCalendarの予定をタスクにします。教科が登録されていなければ、その名前も返します。
+fn from_event(guild_data: &GuildData, event: &IcsEvent) -> Result<(Task, Option<String>), Error> {
+    if let Some(error) = &event.error {
+        anyhow::bail!("{}", error);
+    }
+    let summary = event.summary.as_deref().context("タイトルがありません")?;
+    let (datetime, all_day) = match event.start.context("日時がありません")? {
+        IcsDateTime::DateTime(datetime) => (datetime, false),
+        IcsDateTime::Date(date) => (local(date.and_time(NaiveTime::MIN))?, true),
+    };
+    // 短い予定にもDTENDは付いているので、日をまたぐ場合のみ終わりの日時とする
+    let end = match event.end {
+        Some(IcsDateTime::DateTime(end)) => Some(end),
+        Some(IcsDateTime::Date(date)) => Some(local(
+            date.pred_opt()
+                .context("Invalid DTEND")?
+                .and_time(NaiveTime::MIN),
+        )?),
+        None => None,
+    }
+    .filter(|end| end.date_naive() > datetime.date_naive());
+
+    // 書き出したファイルでは「【カテゴリー】教科 内容」の形式になっている
+    let (label, rest) = match summary.strip_prefix('【').and_then(|s| s.split_once('】')) {
+        Some((label, rest)) => (Some(label), rest.trim()),
+        None => (None, summary.trim()),
+    };
+    let category = event
+        .categories
+        .iter()
+        .map(String::as_str)
+        .chain(label)
+        .find_map(|c| guild_data.match_category(c))
+        .or_else(|| guild_data.fallback_category())
+        .context("カテゴリーが登録されていません")?;
+
+    let mut subject = event
+        .categories
+        .iter()
+        .find_map(|c| guild_data.match_subject(c));
+    let mut details = rest;
+    if let Some((first, remaining)) = rest.split_once(' ') {
+        match (&subject, guild_data.match_subject(first)) {
+            (None, Some(matched)) => {
+                subject = Some(matched);
+                details = remaining;
+            }
+            (Some(subject), Some(matched)) if subject == &matched => details = remaining,
+            _ => {}
+        }
+    }
+    let details = match details.trim() {
+        "" => event
+            .description
+            .clone()
+            .filter(|d| !d.trim().is_empty())
+            .context("内容がありません")?,
+        details => details.to_string(),
+    };
+
+    // 繰り返すタスクの各回は、元のタスクがあれば同じIDにして重複とみなし、なければ1回限りのタスクにする
+    let id = match event.uid.as_deref().and_then(ics::parse_uid) {
+        Some((id, None)) => id,
+        Some((series_id, Some(_))) if guild_data.task(series_id).is_some() => series_id,
+        _ => Uuid::new_v4(),
+    };
+
+    // カテゴリーでも登録済みの教科でもない分類は、登録されていない教科とみなす
+    let unknown = match subject {
+        Some(_) => None,
+        None => event
+            .categories
+            .iter()
+            .find(|c| guild_data.match_category(c).is_none())
+            .cloned(),
+    };
+
+    Ok((
+        Task {
+            category,
+            subject: subject.map_or(Subject::Unset, Subject::Set),
+            details,
+            datetime,
+            end,
+            id,
+            all_day,
+            recurrence: None,
+        },
+        unknown,
+    ))
+}
+
+/// CSVの1行をタスクにします。教科が登録されていなければ、その名前も返します。
+fn from_row(guild_data: &GuildData, row: &CsvRow) -> Result<(Task, Option<String>), Error> {
+    let details = row.details.clone().context("内容がありません")?;
+    // 時刻のない日付は終日のタスクにする
+    let (datetime, all_day) = match (&row.datetime, &row.date) {
+        (Some(datetime), _) => parse_datetime(datetime)?,
+        (None, Some(date)) => {
+            let time = row
+                .time
+                .as_deref()
+                .map(|t| {
+                    NaiveTime::parse_from_str(t, "%H:%M")
+                        .with_context(|| format!("時刻を読み取れませんでした: {}", t))
+                })
+                .transpose()?;
+            (
+                local(parse_date(date)?.and_time(time.unwrap_or(NaiveTime::MIN)))?,
+                time.is_none(),
+            )
+        }
+        (None, None) => anyhow::bail!("日付がありません"),
+    };
+    let category = match &row.category {
+        Some(label) => guild_data
+            .match_category(label)
+            .with_context(|| format!("不明なカテゴリーです: {}", label))?,
+        None => guild_data
+            .fallback_category()
+            .context("カテゴリーが登録されていません")?,
+    };
+    let (subject, unknown) = match &row.subject {
+        Some(name) => match guild_data.match_subject(name) {
+            Some(subject) => (Subject::Set(subject), None),
+            None => (Subject::Unset, Some(name.clone())),
+        },
+        None => (Subject::Unset, None),
+    };
+    let id = row
+        .id
+        .as_deref()
+        .map(|id| {
+            id.parse::<Uuid>()
+                .with_context(|| format!("IDを読み取れませんでした: {}", id))
+        })
+        .transpose()?
+        .unwrap_or_else(Uuid::new_v4);
+
+    Ok((
+        Task {
+            category,
+            subject,
+            details,
+            datetime,
+            end: None,
+            id,
+            all_day,
+            recurrence: None,
+        },
+        unknown,
+    ))
+}
+
+/// 日時を読み取ります。日付のみの場合は終日とします。
+fn parse_datetime(text: &str) -> Result<(DateTime<Local>, bool), Error> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(text) {
+        return Ok((datetime.with_timezone(&Local), false));
+    }
+    let naive = [
+        "%Y/%m/%d %H:%M",
+        "%Y/%m/%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%dT%H:%M:%S",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok());
+    match naive {
+        Some(naive) => Ok((local(naive)?, false)),
+        None => Ok((local(parse_date(text)?.and_time(NaiveTime::MIN))?, true)),
+    }
+}
+
+fn local(naive: NaiveDateTime) -> Result<DateTime<Local>, Error> {
+    Local
+        .from_local_datetime(&naive)
+        .single()
+        .context("Invalid date and time")
+}
+
+fn preview_fields(preview: &Preview) -> Vec<(String, String, bool)> {
+    let task_lines = |tasks: &[Task]| {
+        tasks
+            .iter()
+            .map(|t| format!("{} {}", t.format_datetime(), t.to_field().0))
+            .collect::<Vec<_>>()
+    };
+
+    let mut fields = vec![(
+        format!("追加するタスク ({}件)", preview.new.len()),
+        format_lines(task_lines(&preview.new)),
+        false,
+    )];
+    if !preview.duplicates.is_empty() {
+        fields.push((
+            format!(
+                "重複のため追加しないタスク ({}件)",
+                preview.duplicates.len()
+            ),
+            format_lines(task_lines(&preview.duplicates)),
+            false,
+        ));
+    }
+    if !preview.unknown_subjects.is_empty() {
+        fields.push((
+            "登録されていない教科 (教科なしとして追加します)".into(),
+            format_lines(preview.unknown_subjects.iter().cloned().collect()),
+            false,
+        ));
+    }
+    if !preview.errors.is_empty() {
+        fields.push((
+            format!("読み取れなかった項目 ({}件)", preview.errors.len()),
+            format_lines(preview.errors.clone()),
+            false,
+        ));
+    }
+    fields
+}
+
+fn format_lines(lines: Vec<String>) -> String {
+    if lines.is_empty() {
+        return "なし".into();
+    }
+    let omitted = lines.len().saturating_sub(MAX_PREVIEW_LINES);
+    let mut lines = lines
+        .into_iter()
+        .take(MAX_PREVIEW_LINES)
+        .map(|line| match line.char_indices().nth(MAX_LINE_CHARS) {
+            Some((i, _)) => format!("{}…", &line[..i]),
+            None => line,
+        })
+        .collect::<Vec<_>>();
+    if omitted > 0 {
+        lines.push(format!("…他{}件", omitted));
+    }
+    lines.join("\n")
+}
@@ -4,15 +4,16 @@ use poise::serenity_prelude::*;
 
 use crate::{data, PoiseContext};
 
-#[poise::command(slash_command)]
+#[poise::command(slash_command, guild_only)]
 /// 管理者向けログを送るチャンネルを設定します。
 pub async fn set_log_channel(ctx: PoiseContext<'_>) -> Result<(), Error> {
-    ctx.data()
+    let (guild_id, guild_data) = data::of(ctx)?;
+    guild_data
         .log_channel
         .lock()
         .unwrap()
         .replace(ctx.channel_id());
-    data::save(ctx.data())?;
+    ctx.data().request_save(guild_id);
 
     ctx.send(
         poise::CreateReply::default().embed(
@@ -1,6 +1,19 @@
+pub mod calendar_feed;
+pub mod completion;
+pub mod export_csv;
+pub mod export_ics;
+pub mod import_tasks;
 pub mod log_config;
+pub mod modify_categories;
 pub mod modify_subjects;
 pub mod modify_suggest_times;
 pub mod modify_tasks;
 pub mod panel;
 pub mod ping_config;
+pub mod quick_add;
+pub mod recurrence;
+pub mod restore_backup;
+pub mod task_history;
+pub mod trash;
+pub mod undo;
+pub mod webhooks;
@@ -0,0 +1,195 @@
+use anyhow::{Context as _, Error};
+use poise::serenity_prelude::*;
+
+use crate::{
+    data::{self, CategoryInfo, GuildData},
+    utilities::{autocomplete_category, parse_color, parse_emoji},
+    PoiseContext,
+};
+
+// 絵文字や色の指定を外すときに入力する値
+const CLEAR: &str = "-";
+
+#[poise::command(slash_command, guild_only)]
+/// カテゴリーを追加します。
+pub async fn add_category(
+    ctx: PoiseContext<'_>,
+    #[description = "カテゴリーの名前"] name: String,
+    #[description = "表示する絵文字"] emoji: Option<String>,
+    #[description = "埋め込みの色(例: #ff8800)"] color: Option<String>,
+) -> Result<(), Error> {
+    let (guild_id, guild_data) = data::of(ctx)?;
+    let name = name.trim().to_string();
+    anyhow::ensure!(!name.is_empty(), "名前を入力してください");
+
+    let info = CategoryInfo {
+        emoji: emoji.as_deref().map(parse_emoji).transpose()?,
+        color: color.as_deref().map(parse_color).transpose()?,
+        ..CategoryInfo::new(&name)
+    };
+    guild_data.add_category(info)?;
+    ctx.data().request_save(guild_id);
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("追加しました")
+                .description(category_diff(&guild_data, &name, "+ "))
+                .color(Color::DARK_GREEN),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only)]
+/// カテゴリーの名前・絵文字・色を変更します。
+pub async fn edit_category(
+    ctx: PoiseContext<'_>,
+    #[description = "変更するカテゴリー"]
+    #[autocomplete = "autocomplete_category"]
+    category: String,
+    #[description = "新しい名前"] name: Option<String>,
+    #[description = "表示する絵文字 / 「-」で外します"] emoji: Option<String>,
+    #[description = "埋め込みの色(例: #ff8800) / 「-」で外します"] color: Option<String>,
+) -> Result<(), Error> {
+    let (guild_id, guild_data) = data::of(ctx)?;
+    let category = guild_data
+        .match_category(&category)
+        .context("カテゴリーが見つかりません")?;
+    let current = guild_data.category_info(&category);
+
+    let info = CategoryInfo {
+        name: match name.as_deref().map(str::trim) {
+            Some("") => anyhow::bail!("名前を入力してください"),
+            Some(name) => name.to_string(),
+            None => current.name.clone(),
+        },
+        emoji: match emoji.as_deref() {
+            Some(CLEAR) => None,
+            Some(emoji) => Some(parse_emoji(emoji)?),
+            None => current.emoji.clone(),
+        },
+        color: match color.as_deref() {
+            Some(CLEAR) => None,
+            Some(color) => Some(parse_color(color)?),
+            None => current.color,
+        },
+    };
+    let renamed = guild_data.edit_category(ctx.author().id, &category, info.clone())?;
+    ctx.data().request_save(guild_id);
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("変更しました")
+                .description(format!(
+                    "{} → {}{}",
+                    current.label(),
+                    info.label(),
+                    if renamed > 0 {
+                        format!("\n{}件のタスクのカテゴリーを書き換えました", renamed)
+                    } else {
+                        "".into()
+                    }
+                ))
+                .color(info.color.map_or(Color::DARK_GREEN, Color::new)),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only)]
+/// カテゴリーの並び順を変更します。
+pub async fn move_category(
+    ctx: PoiseContext<'_>,
+    #[description = "移動するカテゴリー"]
+    #[autocomplete = "autocomplete_category"]
+    category: String,
+    #[description = "移動先の順番(1が先頭)"]
+    #[min = 1]
+    position: u32,
+) -> Result<(), Error> {
+    let (guild_id, guild_data) = data::of(ctx)?;
+    let category = guild_data
+        .match_category(&category)
+        .context("カテゴリーが見つかりません")?;
+
+    guild_data.move_category(&category, position as usize - 1)?;
+    ctx.data().request_save(guild_id);
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("並び替えました")
+                .description(category_diff(&guild_data, &category.0, "> "))
+                .color(Color::DARK_GREEN),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only)]
+/// カテゴリーを削除します。
+pub async fn remove_category(
+    ctx: PoiseContext<'_>,
+    #[description = "削除するカテゴリー"]
+    #[autocomplete = "autocomplete_category"]
+    category: String,
+    #[description = "このカテゴリーのタスクの移動先"]
+    #[autocomplete = "autocomplete_category"]
+    move_to: Option<String>,
+) -> Result<(), Error> {
+    let (guild_id, guild_data) = data::of(ctx)?;
+    let category = guild_data
+        .match_category(&category)
+        .context("カテゴリーが見つかりません")?;
+    let move_to = move_to
+        .map(|name| {
+            guild_data
+                .match_category(&name)
+                .with_context(|| format!("移動先のカテゴリーが見つかりません: {}", name))
+        })
+        .transpose()?;
+
+    let diff = category_diff(&guild_data, &category.0, "- ");
+    let moved = guild_data.remove_category(ctx.author().id, &category, move_to.as_ref())?;
+    ctx.data().request_save(guild_id);
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("削除しました")
+                .description(match &move_to {
+                    Some(move_to) if moved > 0 => {
+                        format!("{}\n{}件のタスクを{}に移しました", diff, moved, move_to)
+                    }
+                    _ => diff,
+                })
+                .color(Color::DARK_GREEN),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+// カテゴリーの一覧を、指定したカテゴリーに印を付けてdiff形式で表示する
+fn category_diff(guild_data: &GuildData, name: &str, mark: &str) -> String {
+    format!(
+        "```diff\n{}\n```",
+        guild_data
+            .categories
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|c| format!("{}{}", if c.name == name { mark } else { "" }, c.label()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    )
+}
@@ -4,34 +4,46 @@ use anyhow::{Context as _, Error};
 use futures::StreamExt;
 use poise::serenity_prelude::*;
 
-use crate::{data, PoiseContext};
+use crate::{
+    data::{self, SubjectInfo},
+    utilities::{autocomplete_subject, parse_color, parse_emoji},
+    PoiseContext, Subject, Task,
+};
 
-#[poise::command(slash_command)]
+// 指定を外すときに入力する値
+const CLEAR: &str = "-";
+
+#[poise::command(slash_command, guild_only)]
 /// 教科を追加します。
 pub async fn add_subjects(
     ctx: PoiseContext<'_>,
     #[description = "追加したい教科 / カンマ区切りで複数追加できます"] subjects: String,
 ) -> Result<(), Error> {
+    let (guild_id, guild_data) = data::of(ctx)?;
     let subjects = subjects
         .split(',')
         .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
         .collect::<Vec<_>>();
 
-    ctx.data()
-        .subjects
-        .lock()
-        .unwrap()
-        .extend(subjects.clone().into_iter());
-    data::save(ctx.data())?;
+    let result = subjects
+        .iter()
+        .try_for_each(|s| guild_data.add_subject(SubjectInfo::new(s)).map(|_| ()));
+    ctx.data().request_save(guild_id);
+    result?;
 
     let diff = format!(
         "```diff\n{}\n```",
-        ctx.data()
+        guild_data
             .subjects
             .lock()
             .unwrap()
             .iter()
-            .map(|s| format!("{}{}", if subjects.contains(s) { "+ " } else { "" }, s))
+            .map(|s| format!(
+                "{}{}",
+                if subjects.contains(&s.name) { "+ " } else { "" },
+                s.label()
+            ))
             .collect::<Vec<_>>()
             .join("\n")
     );
@@ -49,21 +61,22 @@ pub async fn add_subjects(
     Ok(())
 }
 
-#[poise::command(slash_command)]
+#[poise::command(slash_command, guild_only)]
 /// 教科を削除します。
 pub async fn remove_subject(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let (guild_id, guild_data) = data::of(ctx)?;
     const SUBJECT: &str = "subject";
     const SUBMIT: &str = "submit";
 
-    let subjects = ctx.data().subjects.lock().unwrap().clone();
+    let subjects = guild_data.subjects.lock().unwrap().clone();
 
     let components = |selected_subject: Option<String>| {
         let subject_options = CreateSelectMenuKind::String {
             options: subjects
                 .iter()
                 .map(|s| {
-                    CreateSelectMenuOption::new(s, s)
-                        .default_selection(selected_subject == Some(s.clone()))
+                    CreateSelectMenuOption::new(s.label(), &s.name)
+                        .default_selection(selected_subject.as_ref() == Some(&s.name))
                 })
                 .collect(),
         };
@@ -114,51 +127,480 @@ pub async fn remove_subject(ctx: PoiseContext<'_>) -> Result<(), Error> {
                 );
                 interaction.create_response(&ctx, response).await?;
             }
-            ComponentInteractionDataKind::Button => {
-                if interaction.data.custom_id == SUBMIT {
-                    last_interaction.replace(interaction);
-                    break;
-                }
+            ComponentInteractionDataKind::Button if interaction.data.custom_id == SUBMIT => {
+                last_interaction.replace(interaction);
+                break;
             }
             _ => {}
         }
     }
 
     let subject = select.context("Subject not selected")?;
+    let last_interaction = last_interaction.context("No interaction")?;
+
+    // タスクが残っている場合は、移動先を選ばせる
+    let dependents = guild_data
+        .tasks
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|task| task.subject == Subject::Set(subject.clone()))
+        .cloned()
+        .collect::<Vec<_>>();
+    let (last_interaction, move_to) = if dependents.is_empty() {
+        (last_interaction, None)
+    } else {
+        match select_move_to(ctx, last_interaction, &subject, &dependents).await? {
+            Some((interaction, move_to)) => (interaction, Some(move_to)),
+            None => return Ok(()),
+        }
+    };
+
     let diff = format!(
         "```diff\n{}\n```",
-        ctx.data()
+        guild_data
             .subjects
             .lock()
             .unwrap()
             .iter()
-            .map(|s| format!("{}{}", if s == &subject { "- " } else { "" }, s))
+            .map(|s| format!("{}{}", if s.name == subject { "- " } else { "" }, s.label()))
             .collect::<Vec<_>>()
             .join("\n")
     );
 
-    ctx.data()
+    let moved = guild_data.remove_subject(ctx.author().id, &subject, move_to.as_ref())?;
+    ctx.data().request_save(guild_id);
+
+    let response = CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::default()
+            .embed(
+                CreateEmbed::default()
+                    .title("削除しました")
+                    .description(match &move_to {
+                        Some(Subject::Set(move_to)) => {
+                            format!("{}\n{}件のタスクを{}に移しました", diff, moved, move_to)
+                        }
+                        Some(Subject::Unset) => {
+                            format!("{}\n{}件のタスクを教科なしにしました", diff, moved)
+                        }
+                        None => diff,
+                    })
+                    .color(Color::DARK_GREEN),
+            )
+            .components(vec![]),
+    );
+    last_interaction.create_response(&ctx, response).await?;
+
+    Ok(())
+}
+
+// 削除する教科のタスクを表示し、移動先の教科か教科なしを選ばせる
+// キャンセルされた場合はメッセージを更新してNoneを返す
+async fn select_move_to(
+    ctx: PoiseContext<'_>,
+    interaction: ComponentInteraction,
+    subject: &str,
+    dependents: &[Task],
+) -> Result<Option<(ComponentInteraction, Subject)>, Error> {
+    const MOVE_TO: &str = "move_to";
+    const SUBMIT: &str = "submit";
+    const UNSET: &str = "unset";
+    const CANCEL: &str = "cancel";
+
+    let (_, guild_data) = data::of(ctx)?;
+    let subjects = guild_data
         .subjects
         .lock()
         .unwrap()
-        .retain(|s| s != &subject);
-    data::save(ctx.data())?;
+        .iter()
+        .filter(|s| s.name != subject)
+        .take(25)
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let embed = CreateEmbed::default()
+        .title("この教科のタスクが残っています")
+        .description(format!(
+            "{}を削除する前に、タスクの移動先を選んでください",
+            subject
+        ))
+        .fields(
+            dependents
+                .iter()
+                .take(24)
+                .map(|task| guild_data.task_field(task)),
+        )
+        .footer(CreateEmbedFooter::new(format!("全{}件", dependents.len())))
+        .color(Color::ORANGE);
+    let components = |selected: Option<&String>| {
+        let mut components = vec![];
+        if !subjects.is_empty() {
+            components.push(CreateActionRow::SelectMenu(
+                CreateSelectMenu::new(
+                    MOVE_TO,
+                    CreateSelectMenuKind::String {
+                        options: subjects
+                            .iter()
+                            .map(|s| {
+                                CreateSelectMenuOption::new(s.label(), &s.name)
+                                    .default_selection(selected == Some(&s.name))
+                            })
+                            .collect(),
+                    },
+                )
+                .placeholder("移動先の教科を選択してください"),
+            ));
+        }
+        components.push(CreateActionRow::Buttons(vec![
+            CreateButton::new(SUBMIT)
+                .label("移動して削除")
+                .style(ButtonStyle::Danger)
+                .disabled(selected.is_none()),
+            CreateButton::new(UNSET)
+                .label("教科なしにして削除")
+                .style(ButtonStyle::Danger),
+            CreateButton::new(CANCEL)
+                .label("キャンセル")
+                .style(ButtonStyle::Secondary),
+        ]));
+        components
+    };
+
+    let response = CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::default()
+            .embed(embed)
+            .components(components(None)),
+    );
+    interaction.create_response(ctx, response).await?;
+
+    let mut interaction_stream = interaction
+        .get_response(ctx)
+        .await?
+        .await_component_interaction(ctx)
+        .author_id(ctx.author().id)
+        .timeout(Duration::from_secs(60 * 30))
+        .stream();
+
+    let mut selected = None;
+    while let Some(interaction) = interaction_stream.next().await {
+        match &interaction.data.kind {
+            ComponentInteractionDataKind::StringSelect { values } => {
+                selected.replace(values[0].clone());
+                let response = CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::default()
+                        .components(components(selected.as_ref())),
+                );
+                interaction.create_response(ctx, response).await?;
+            }
+            ComponentInteractionDataKind::Button => match interaction.data.custom_id.as_str() {
+                SUBMIT => {
+                    let move_to = selected.context("Subject not selected")?;
+                    return Ok(Some((interaction, Subject::Set(move_to))));
+                }
+                UNSET => return Ok(Some((interaction, Subject::Unset))),
+                _ => {
+                    let response = CreateInteractionResponse::UpdateMessage(
+                        CreateInteractionResponseMessage::default()
+                            .embed(
+                                CreateEmbed::default()
+                                    .title("削除をキャンセルしました")
+                                    .color(Color::DARK_GREY),
+                            )
+                            .components(vec![]),
+                    );
+                    interaction.create_response(ctx, response).await?;
+                    return Ok(None);
+                }
+            },
+            _ => {}
+        }
+    }
+    anyhow::bail!("No interaction")
+}
+
+#[poise::command(slash_command, guild_only)]
+/// 登録されている教科の一覧を表示します。
+pub async fn subjects(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let (_, guild_data) = data::of(ctx)?;
+    let subjects = guild_data.subjects.lock().unwrap().clone();
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("教科一覧")
+                .description(if subjects.is_empty() {
+                    "登録されていません"
+                } else {
+                    ""
+                })
+                .fields(subjects.iter().take(25).map(|s| {
+                    let mut details = vec![];
+                    if let Some(teacher) = &s.teacher {
+                        details.push(format!("担当: {}", teacher));
+                    }
+                    if let Some(short_code) = &s.short_code {
+                        details.push(format!("略称: {}", short_code));
+                    }
+                    if !s.aliases.is_empty() {
+                        details.push(format!("別名: {}", s.aliases.join(", ")));
+                    }
+                    if let Some(color) = s.color {
+                        details.push(format!("色: #{:06x}", color));
+                    }
+                    (s.label(), details.join("\n"), true)
+                }))
+                .footer(CreateEmbedFooter::new(format!("全{}件", subjects.len())))
+                .color(Color::DARK_BLUE),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only)]
+/// 教科の担当・色・絵文字・略称・別名を変更します。
+pub async fn edit_subject(
+    ctx: PoiseContext<'_>,
+    #[description = "変更する教科"]
+    #[autocomplete = "autocomplete_subject"]
+    subject: String,
+    #[description = "担当の先生 / 「-」で外します"] teacher: Option<String>,
+    #[description = "埋め込みの色(例: #ff8800) / 「-」で外します"] color: Option<String>,
+    #[description = "表示する絵文字 / 「-」で外します"] emoji: Option<String>,
+    #[description = "略称(例: 数I) / 「-」で外します"] short_code: Option<String>,
+    #[description = "検索に使う別名 / カンマ区切り / 「-」で外します"] aliases: Option<String>,
+) -> Result<(), Error> {
+    let (guild_id, guild_data) = data::of(ctx)?;
+    let name = guild_data
+        .match_subject(&subject)
+        .context("教科が見つかりません")?;
+    let current = guild_data
+        .subject_info(&name)
+        .context("教科が見つかりません")?;
+
+    let text = |value: Option<String>, current: &Option<String>| match value.as_deref() {
+        Some(CLEAR) => None,
+        Some(value) => Some(value.trim().to_string()).filter(|v| !v.is_empty()),
+        None => current.clone(),
+    };
+    let info = SubjectInfo {
+        name: current.name.clone(),
+        teacher: text(teacher, &current.teacher),
+        color: match color.as_deref() {
+            Some(CLEAR) => None,
+            Some(color) => Some(parse_color(color)?),
+            None => current.color,
+        },
+        emoji: match emoji.as_deref() {
+            Some(CLEAR) => None,
+            Some(emoji) => Some(parse_emoji(emoji)?),
+            None => current.emoji.clone(),
+        },
+        short_code: text(short_code, &current.short_code),
+        aliases: match aliases.as_deref() {
+            Some(CLEAR) => vec![],
+            Some(aliases) => aliases
+                .split(',')
+                .map(|a| a.trim().to_string())
+                .filter(|a| !a.is_empty())
+                .collect(),
+            None => current.aliases.clone(),
+        },
+    };
+    guild_data.update_subject(info.clone())?;
+    ctx.data().request_save(guild_id);
+
+    let describe = |s: &SubjectInfo| {
+        format!(
+            "担当: {}\n略称: {}\n別名: {}\n色: {}",
+            s.teacher.as_deref().unwrap_or("なし"),
+            s.short_code.as_deref().unwrap_or("なし"),
+            if s.aliases.is_empty() {
+                "なし".into()
+            } else {
+                s.aliases.join(", ")
+            },
+            s.color.map_or("なし".into(), |c| format!("#{:06x}", c)),
+        )
+    };
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("教科を変更しました")
+                .fields(vec![
+                    (current.label(), describe(&current), true),
+                    ("→".into(), "".into(), true),
+                    (info.label(), describe(&info), true),
+                ])
+                .color(info.color.map_or(Color::DARK_GREEN, Color::new)),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only)]
+/// 教科の名前を変更し、その教科のタスクをすべて書き換えます。
+pub async fn rename_subject(
+    ctx: PoiseContext<'_>,
+    #[description = "変更する教科"]
+    #[autocomplete = "autocomplete_subject"]
+    subject: String,
+    #[description = "新しい名前"] name: String,
+) -> Result<(), Error> {
+    let (guild_id, guild_data) = data::of(ctx)?;
+    let subject = guild_data
+        .match_subject(&subject)
+        .context("教科が見つかりません")?;
+    let name = name.trim().to_string();
+    anyhow::ensure!(!name.is_empty(), "名前を入力してください");
+    anyhow::ensure!(name != subject, "今の名前と同じです");
+    if let Some(other) = guild_data.match_subject(&name).filter(|s| s != &subject) {
+        anyhow::bail!("「{}」は{}の名前と重なっています", name, other);
+    }
+
+    let count = guild_data.subject_task_count(&subject);
+    let Some(interaction) = confirm(
+        ctx,
+        CreateEmbed::default()
+            .title("教科の名前を変更しますか？")
+            .description(format!(
+                "{} → {}\n{}件のタスクが書き換えられます",
+                subject, name, count
+            ))
+            .color(Color::DARK_BLUE),
+        "変更する",
+    )
+    .await?
+    else {
+        return Ok(());
+    };
+
+    let renamed = guild_data.rename_subject(ctx.author().id, &subject, &name)?;
+    ctx.data().request_save(guild_id);
 
     let response = CreateInteractionResponse::UpdateMessage(
         CreateInteractionResponseMessage::default()
             .embed(
                 CreateEmbed::default()
-                    .title("削除しました")
-                    .description(diff)
+                    .title("教科の名前を変更しました")
+                    .description(format!(
+                        "{} → {}\n{}件のタスクを書き換えました",
+                        subject, name, renamed
+                    ))
                     .color(Color::DARK_GREEN),
             )
             .components(vec![]),
     );
+    interaction.create_response(ctx, response).await?;
 
-    last_interaction
-        .context("No interaction")?
-        .create_response(&ctx, response)
-        .await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only)]
+/// 教科を別の教科に統合し、その教科のタスクをすべて書き換えます。
+pub async fn merge_subjects(
+    ctx: PoiseContext<'_>,
+    #[description = "統合する教科 / この教科はなくなります"]
+    #[autocomplete = "autocomplete_subject"]
+    from: String,
+    #[description = "統合先の教科"]
+    #[autocomplete = "autocomplete_subject"]
+    into: String,
+) -> Result<(), Error> {
+    let (guild_id, guild_data) = data::of(ctx)?;
+    let from = guild_data
+        .match_subject(&from)
+        .context("統合する教科が見つかりません")?;
+    let into = guild_data
+        .match_subject(&into)
+        .context("統合先の教科が見つかりません")?;
+    anyhow::ensure!(from != into, "同じ教科には統合できません");
+
+    let count = guild_data.subject_task_count(&from);
+    let Some(interaction) = confirm(
+        ctx,
+        CreateEmbed::default()
+            .title("教科を統合しますか？")
+            .description(format!(
+                "{} → {}\n{}件のタスクが書き換えられます\n{}は{}の別名として残ります",
+                from, into, count, from, into
+            ))
+            .color(Color::DARK_BLUE),
+        "統合する",
+    )
+    .await?
+    else {
+        return Ok(());
+    };
+
+    let merged = guild_data.merge_subject(ctx.author().id, &from, &into)?;
+    ctx.data().request_save(guild_id);
+
+    let response = CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::default()
+            .embed(
+                CreateEmbed::default()
+                    .title("教科を統合しました")
+                    .description(format!(
+                        "{} → {}\n{}件のタスクを書き換えました",
+                        from, into, merged
+                    ))
+                    .color(Color::DARK_GREEN),
+            )
+            .components(vec![]),
+    );
+    interaction.create_response(ctx, response).await?;
 
     Ok(())
 }
+
+// 確認のメッセージを送り、実行が選ばれたらそのインタラクションを返す
+// キャンセルされた場合はメッセージを更新してNoneを返す
+async fn confirm(
+    ctx: PoiseContext<'_>,
+    embed: CreateEmbed,
+    label: &str,
+) -> Result<Option<ComponentInteraction>, Error> {
+    const CONFIRM: &str = "confirm";
+    const CANCEL: &str = "cancel";
+
+    let message = ctx
+        .send(poise::CreateReply::default().embed(embed).components(vec![
+            CreateActionRow::Buttons(vec![
+                    CreateButton::new(CONFIRM)
+                        .label(label)
+                        .style(ButtonStyle::Danger),
+                    CreateButton::new(CANCEL)
+                        .label("キャンセル")
+                        .style(ButtonStyle::Secondary),
+                ]),
+        ]))
+        .await?
+        .into_message()
+        .await?;
+
+    let interaction = message
+        .await_component_interaction(ctx)
+        .author_id(ctx.author().id)
+        .timeout(Duration::from_secs(60 * 30))
+        .await
+        .context("No interaction")?;
+
+    if interaction.data.custom_id != CONFIRM {
+        let response = CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::default()
+                .embed(
+                    CreateEmbed::default()
+                        .title("キャンセルしました")
+                        .color(Color::DARK_GREY),
+                )
+                .components(vec![]),
+        );
+        interaction.create_response(ctx, response).await?;
+        return Ok(None);
+    }
+    Ok(Some(interaction))
+}
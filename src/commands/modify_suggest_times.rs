@@ -8,12 +8,19 @@ use poise::serenity_prelude::*;
 
 use crate::{data, interactions::select_time, PoiseContext};
 
-#[poise::command(slash_command)]
+#[poise::command(slash_command, guild_only)]
 /// よく使う時間を追加します。
 pub async fn add_suggest_time(
     ctx: PoiseContext<'_>,
     #[description = "よく使う時間のラベル(例: 1限開始時刻)"] label: String,
 ) -> Result<(), Error> {
+    let (guild_id, guild_data) = data::of(ctx)?;
+    if guild_data.suggest_times.lock().unwrap().len() >= data::MAX_SUGGEST_TIMES {
+        anyhow::bail!(
+            "よく使う時間は{}件までしか登録できません",
+            data::MAX_SUGGEST_TIMES
+        );
+    }
     let (interaction, time) = select_time(
         ctx,
         None,
@@ -25,17 +32,17 @@ pub async fn add_suggest_time(
     )
     .await?;
 
-    ctx.data()
+    guild_data
         .suggest_times
         .lock()
         .unwrap()
         .insert(time, label.clone());
-    data::save(ctx.data())?;
+    ctx.data().request_save(guild_id);
 
     let title = format!("{}({})を追加しました", label, time.format("%H:%M"));
     let diff = format!(
         "```diff\n{}\n```",
-        ctx.data()
+        guild_data
             .suggest_times
             .lock()
             .unwrap()
@@ -66,13 +73,14 @@ pub async fn add_suggest_time(
     Ok(())
 }
 
-#[poise::command(slash_command)]
+#[poise::command(slash_command, guild_only)]
 /// よく使う時間を削除します。
 pub async fn remove_suggest_time(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let (guild_id, guild_data) = data::of(ctx)?;
     const LABEL: &str = "label";
     const SUBMIT: &str = "submit";
 
-    let suggest_times = ctx.data().suggest_times.lock().unwrap().clone();
+    let suggest_times = guild_data.suggest_times.lock().unwrap().clone();
 
     let components = |selected_time: Option<NaiveTime>| {
         let suggest_time_options = CreateSelectMenuKind::String {
@@ -130,11 +138,9 @@ pub async fn remove_suggest_time(ctx: PoiseContext<'_>) -> Result<(), Error> {
                 );
                 interaction.create_response(ctx, response).await?;
             }
-            ComponentInteractionDataKind::Button => {
-                if interaction.data.custom_id == SUBMIT {
-                    last_interaction.replace(interaction);
-                    break;
-                }
+            ComponentInteractionDataKind::Button if interaction.data.custom_id == SUBMIT => {
+                last_interaction.replace(interaction);
+                break;
             }
             _ => {}
         }
@@ -148,7 +154,7 @@ pub async fn remove_suggest_time(ctx: PoiseContext<'_>) -> Result<(), Error> {
     );
     let diff = format!(
         "```diff\n{}\n```",
-        ctx.data()
+        guild_data
             .suggest_times
             .lock()
             .unwrap()
@@ -163,8 +169,8 @@ pub async fn remove_suggest_time(ctx: PoiseContext<'_>) -> Result<(), Error> {
             .join("\n")
     );
 
-    ctx.data().suggest_times.lock().unwrap().remove(&time);
-    data::save(ctx.data())?;
+    guild_data.suggest_times.lock().unwrap().remove(&time);
+    ctx.data().request_save(guild_id);
 
     let response = CreateInteractionResponse::UpdateMessage(
         CreateInteractionResponseMessage::default()
@@ -2,14 +2,17 @@ use anyhow::Error;
 use poise::serenity_prelude::*;
 
 use crate::{
+    commands::undo::{listen_undo, undo_button},
     data,
-    interactions::{create_task, select_task},
+    interactions::{create_task, select_occurrence, select_task},
+    utilities::format_date,
     PartialTask, PoiseContext,
 };
 
-#[poise::command(slash_command)]
+#[poise::command(slash_command, guild_only)]
 /// タスクを追加します。
 pub async fn add_task(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let (guild_id, guild_data) = data::of(ctx)?;
     let (last_interaction, task) = create_task(
         ctx,
         None,
@@ -22,27 +25,31 @@ pub async fn add_task(ctx: PoiseContext<'_>) -> Result<(), Error> {
     )
     .await?;
 
-    ctx.data().tasks.lock().unwrap().insert(task.clone());
-    data::save(ctx.data())?;
+    let event = guild_data.add_task(ctx.author().id, task.clone());
+    ctx.data().request_save(guild_id);
 
     let response = CreateInteractionResponse::UpdateMessage(
         CreateInteractionResponseMessage::default()
             .embed(
                 CreateEmbed::default()
                     .title("タスクを追加しました")
-                    .fields(vec![task.to_field()])
+                    .fields(vec![guild_data.task_field(&task)])
                     .color(Color::DARK_GREEN),
             )
-            .components(vec![]),
+            .components(vec![CreateActionRow::Buttons(vec![undo_button()])]),
     );
     last_interaction.create_response(ctx, response).await?;
 
+    let message = last_interaction.get_response(ctx).await?;
+    listen_undo(ctx, message, event.id).await?;
+
     Ok(())
 }
 
-#[poise::command(slash_command)]
+#[poise::command(slash_command, guild_only)]
 /// タスクを削除します。
 pub async fn remove_task(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let (guild_id, guild_data) = data::of(ctx)?;
     let (last_interaction, task) = select_task(
         ctx,
         None,
@@ -54,30 +61,31 @@ pub async fn remove_task(ctx: PoiseContext<'_>) -> Result<(), Error> {
     )
     .await?;
 
-    {
-        let mut tasks = ctx.data().tasks.lock().unwrap();
-        tasks.remove(&task);
-    }
-    data::save(ctx.data())?;
+    let event = guild_data.remove_task(ctx.author().id, task.id)?;
+    ctx.data().request_save(guild_id);
 
     let response = CreateInteractionResponse::UpdateMessage(
         CreateInteractionResponseMessage::default()
             .embed(
                 CreateEmbed::default()
                     .title("削除しました")
-                    .fields(vec![task.to_field()])
+                    .fields(vec![guild_data.task_field(&task)])
                     .color(Color::DARK_RED),
             )
-            .components(vec![]),
+            .components(vec![CreateActionRow::Buttons(vec![undo_button()])]),
     );
     last_interaction.create_response(ctx, response).await?;
 
+    let message = last_interaction.get_response(ctx).await?;
+    listen_undo(ctx, message, event.id).await?;
+
     Ok(())
 }
 
-#[poise::command(slash_command)]
+#[poise::command(slash_command, guild_only)]
 /// タスクを編集します。
 pub async fn edit_task(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let (guild_id, guild_data) = data::of(ctx)?;
     let (last_interaction, task) = select_task(
         ctx,
         None,
@@ -89,6 +97,35 @@ pub async fn edit_task(ctx: PoiseContext<'_>) -> Result<(), Error> {
     )
     .await?;
 
+    // 繰り返すタスクは、この回のみかすべての回かを選ぶ
+    let (last_interaction, occurrence) = match task.recurrence {
+        Some(_) => {
+            select_occurrence(
+                ctx,
+                last_interaction,
+                &task,
+                CreateEmbed::default()
+                    .title("どの回を編集しますか？")
+                    .fields(vec![guild_data.task_field(&task)])
+                    .color(Color::DARK_BLUE),
+            )
+            .await?
+        }
+        None => (last_interaction, None),
+    };
+    let defaults = match occurrence {
+        Some(date) => PartialTask {
+            date: Some(date),
+            // 期間のあるタスクは、この回の終わりの日付にずらす
+            end_date: task
+                .end
+                .map(|end| date + (end.date_naive() - task.datetime.date_naive())),
+            recurrence: None,
+            ..task.as_partial()
+        },
+        None => task.as_partial(),
+    };
+
     let (last_interaction, modified_task) = create_task(
         ctx,
         Some(last_interaction),
@@ -97,32 +134,42 @@ pub async fn edit_task(ctx: PoiseContext<'_>) -> Result<(), Error> {
                 .title("タスクを編集します".to_string())
                 .color(Color::DARK_BLUE),
         ),
-        task.as_partial(),
+        defaults,
     )
     .await?;
 
-    {
-        let mut tasks = ctx.data().tasks.lock().unwrap();
-        tasks.remove(&task);
-        tasks.insert(modified_task.clone());
-    }
-    data::save(ctx.data())?;
+    // 繰り返しから除いた場合も、2つの変更は同じbatchなのでまとめて取り消される
+    let event = match occurrence {
+        Some(date) => {
+            guild_data
+                .detach_occurrence(ctx.author().id, task.id, date, modified_task.clone())?
+                .0
+        }
+        None => guild_data.edit_task(ctx.author().id, modified_task.clone())?,
+    };
+    ctx.data().request_save(guild_id);
 
     let response = CreateInteractionResponse::UpdateMessage(
         CreateInteractionResponseMessage::default()
             .embed(
                 CreateEmbed::default()
-                    .title("タスクを編集しました")
+                    .title(match occurrence {
+                        Some(date) => format!("{}の回を編集しました", format_date(date)),
+                        None => "タスクを編集しました".into(),
+                    })
                     .fields(vec![
-                        task.to_field(),
+                        guild_data.task_field(&task),
                         ("↓".into(), "".into(), false),
-                        modified_task.to_field(),
+                        guild_data.task_field(&modified_task),
                     ])
                     .color(Color::DARK_GREEN),
             )
-            .components(vec![]),
+            .components(vec![CreateActionRow::Buttons(vec![undo_button()])]),
     );
     last_interaction.create_response(ctx, response).await?;
 
+    let message = last_interaction.get_response(ctx).await?;
+    listen_undo(ctx, message, event.id).await?;
+
     Ok(())
 }
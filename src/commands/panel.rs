@@ -1,20 +1,25 @@
 use std::time::Duration;
 
 use anyhow::{Context as _, Error};
-use chrono::Local;
-use itertools::Itertools;
+use chrono::{Local, NaiveTime};
 use poise::serenity_prelude::*;
 use {futures::StreamExt, Mentionable};
 
-use crate::{data, PoiseContext};
+use crate::{
+    commands::completion,
+    data::{self, Data, GuildData},
+    utilities::truncate,
+    PoiseContext,
+};
 
 const TASKS: &str = "tasks";
 const ARCHIVED_TASKS: &str = "archived_tasks";
 const TASKS_PER_PAGE: usize = 7;
 
-#[poise::command(slash_command)]
+#[poise::command(slash_command, guild_only)]
 /// パネルをデプロイします。
 pub async fn deploy_panel(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let (guild_id, guild_data) = data::of(ctx)?;
     let message = ctx
         .channel_id()
         .send_message(
@@ -39,21 +44,22 @@ pub async fn deploy_panel(ctx: PoiseContext<'_>) -> Result<(), Error> {
 
     let id_pair = (message.id, message.channel_id);
 
-    ctx.data().panel_message.lock().unwrap().replace(id_pair);
-    data::save(ctx.data())?;
+    guild_data.panel_message.lock().unwrap().replace(id_pair);
+    ctx.data().request_save(guild_id);
 
-    ctx.data()
+    guild_data
         .panel_listener
         .lock()
         .unwrap()
         .as_ref()
         .inspect(|h| h.abort());
-    ctx.data()
+    guild_data
         .panel_listener
         .lock()
         .unwrap()
         .replace(tokio::spawn(listen_panel_interactions(
             ctx.serenity_context().clone(),
+            ctx.data().clone(),
             id_pair,
         )));
 
@@ -73,6 +79,7 @@ pub async fn deploy_panel(ctx: PoiseContext<'_>) -> Result<(), Error> {
 
 pub async fn listen_panel_interactions(
     ctx: Context,
+    data: Data,
     id_pair: (MessageId, ChannelId),
 ) -> Result<(), Error> {
     let (message_id, channel_id) = id_pair;
@@ -82,10 +89,14 @@ pub async fn listen_panel_interactions(
     while let Some(interaction) = interaction_stream.next().await {
         match interaction.data.custom_id.as_str() {
             TASKS => {
-                tokio::spawn(show_tasks(interaction.clone(), ctx.clone()));
+                tokio::spawn(show_tasks(interaction.clone(), ctx.clone(), data.clone()));
             }
             ARCHIVED_TASKS => {
-                tokio::spawn(show_archived_tasks(interaction.clone(), ctx.clone()));
+                tokio::spawn(show_archived_tasks(
+                    interaction.clone(),
+                    ctx.clone(),
+                    data.clone(),
+                ));
             }
             _ => {}
         }
@@ -94,8 +105,13 @@ pub async fn listen_panel_interactions(
     Ok(())
 }
 
-async fn log(ctx: &Context, user: &User, message: impl Into<String>) -> Result<(), Error> {
-    let log_channel = *data::load()?.log_channel.lock().unwrap();
+async fn log(
+    ctx: &Context,
+    guild_data: &GuildData,
+    user: &User,
+    message: impl Into<String>,
+) -> Result<(), Error> {
+    let log_channel = *guild_data.log_channel.lock().unwrap();
 
     log_channel
         .context("log channel not set")?
@@ -119,51 +135,133 @@ async fn log(ctx: &Context, user: &User, message: impl Into<String>) -> Result<(
     Ok(())
 }
 
-async fn show_tasks(interaction: ComponentInteraction, ctx: Context) -> Result<(), Error> {
+async fn show_tasks(
+    interaction: ComponentInteraction,
+    ctx: Context,
+    data: Data,
+) -> Result<(), Error> {
     const PREV: &str = "prev";
     const NEXT: &str = "next";
+    const COMPLETE: &str = "complete";
+    const HIDE_COMPLETED: &str = "hide_completed";
 
+    let guild_id = interaction.guild_id.context("Not in a guild")?;
+    let user_id = interaction.user.id;
     let mut page = 0;
-    let message = |page: usize| -> Result<_, Error> {
-        let tasks = data::load()?.tasks.lock().unwrap().clone();
-        let fields = tasks
-            .iter()
-            .filter(|e| Local::now().date_naive() <= e.datetime.date_naive())
-            .sorted_by_key(|e| e.datetime)
-            .map(|task| task.to_field())
-            .skip(TASKS_PER_PAGE * page);
+    // 自分が完了したタスクを表示しない
+    let mut hide_completed = false;
+    let page_tasks = |page: usize, hide_completed: bool| -> Result<_, Error> {
+        let today = Local::now()
+            .with_time(NaiveTime::MIN)
+            .single()
+            .context("Invalid date")?;
+        let guild_data = data.guild(guild_id);
+        let tasks = guild_data
+            .tasks_in(today.., None)
+            .into_iter()
+            .filter(|task| !hide_completed || !guild_data.is_completed(task, user_id))
+            .collect::<Vec<_>>();
+        let has_next = tasks.len() > TASKS_PER_PAGE * (page + 1);
+        let tasks = tasks
+            .into_iter()
+            .skip(TASKS_PER_PAGE * page)
+            .take(TASKS_PER_PAGE)
+            .map(|task| {
+                let completed = guild_data.is_completed(&task, user_id);
+                (task, completed)
+            })
+            .collect::<Vec<_>>();
+        Ok((tasks, has_next))
+    };
+    let message = |page: usize, hide_completed: bool| -> Result<_, Error> {
+        let (tasks, has_next) = page_tasks(page, hide_completed)?;
+        let guild_data = data.guild(guild_id);
+        // タスクごとに埋め込みを分けて、教科やカテゴリーの色を付ける
+        let task_embeds = tasks.iter().map(|(task, completed)| {
+            let (name, value, _) = guild_data.task_field(task);
+            CreateEmbed::default()
+                .title(if *completed {
+                    format!("✅ {}", name)
+                } else {
+                    name
+                })
+                .description(value)
+                .color(guild_data.task_color(task).unwrap_or(Color::DARK_BLUE))
+        });
+
+        let mut components = vec![];
+        if !tasks.is_empty() {
+            components.push(CreateActionRow::SelectMenu(
+                CreateSelectMenu::new(
+                    COMPLETE,
+                    CreateSelectMenuKind::String {
+                        options: tasks
+                            .iter()
+                            .map(|(task, completed)| {
+                                CreateSelectMenuOption::new(
+                                    truncate(&task.to_field().0, 100),
+                                    completion::task_key(task),
+                                )
+                                .description(if *completed {
+                                    "完了済み / 選ぶと未完了に戻します"
+                                } else {
+                                    "未完了 / 選ぶと完了にします"
+                                })
+                            })
+                            .collect(),
+                    },
+                )
+                .placeholder("完了を切り替える"),
+            ));
+        }
+        components.push(CreateActionRow::Buttons(vec![
+            CreateButton::new(PREV)
+                .label("前のページ")
+                .style(ButtonStyle::Secondary)
+                .disabled(page == 0),
+            CreateButton::new(NEXT)
+                .label("次のページ")
+                .style(ButtonStyle::Secondary)
+                .disabled(!has_next),
+            CreateButton::new(HIDE_COMPLETED)
+                .label(if hide_completed {
+                    "すべて表示"
+                } else {
+                    "未完了のみ表示"
+                })
+                .style(ButtonStyle::Primary),
+        ]));
 
         Ok(CreateInteractionResponseMessage::new()
             .embed(
                 CreateEmbed::default()
-                    .title("タスク一覧")
-                    .description(if fields.len() == 0 {
+                    .title(if hide_completed {
+                        "タスク一覧 (未完了のみ)"
+                    } else {
+                        "タスク一覧"
+                    })
+                    .description(if tasks.is_empty() {
                         "ありません！:tada:"
                     } else {
                         ""
                     })
-                    .fields(fields.clone().take(TASKS_PER_PAGE))
                     .color(Color::DARK_BLUE),
             )
-            .components(vec![CreateActionRow::Buttons(vec![
-                CreateButton::new(PREV)
-                    .label("前のページ")
-                    .style(ButtonStyle::Secondary)
-                    .disabled(page == 0),
-                CreateButton::new(NEXT)
-                    .label("次のページ")
-                    .style(ButtonStyle::Secondary)
-                    .disabled(fields.len() <= TASKS_PER_PAGE),
-            ])])
+            .add_embeds(task_embeds.collect())
+            .components(components)
             .ephemeral(true))
     };
 
     interaction
-        .create_response(&ctx, CreateInteractionResponse::Message(message(page)?))
+        .create_response(
+            &ctx,
+            CreateInteractionResponse::Message(message(page, hide_completed)?),
+        )
         .await?;
 
     log(
         &ctx,
+        &data.guild(guild_id),
         &interaction.user,
         format!(
             "{}さんがタスク一覧を確認しました",
@@ -180,45 +278,65 @@ async fn show_tasks(interaction: ComponentInteraction, ctx: Context) -> Result<(
         .stream();
 
     while let Some(interaction) = interaction_stream.next().await {
-        match interaction.data.custom_id.as_str() {
-            PREV => {
-                page = page.saturating_sub(1);
-                interaction
-                    .create_response(
-                        &ctx,
-                        CreateInteractionResponse::UpdateMessage(message(page)?),
-                    )
-                    .await?;
-            }
-            NEXT => {
-                page += 1;
-                interaction
-                    .create_response(
-                        &ctx,
-                        CreateInteractionResponse::UpdateMessage(message(page)?),
-                    )
-                    .await?;
+        match &interaction.data.kind {
+            ComponentInteractionDataKind::StringSelect { values }
+                if interaction.data.custom_id == COMPLETE =>
+            {
+                let guild_data = data.guild(guild_id);
+                if let Some(task) = completion::find_task(&guild_data, &values[0]) {
+                    guild_data.toggle_completion(&task, user_id);
+                    data.request_save(guild_id);
+                }
+                // 完了にしたタスクが消えてページが空になった場合は前のページに戻る
+                while page > 0 && page_tasks(page, hide_completed)?.0.is_empty() {
+                    page -= 1;
+                }
             }
-            _ => {}
+            ComponentInteractionDataKind::Button => match interaction.data.custom_id.as_str() {
+                PREV => page = page.saturating_sub(1),
+                NEXT => page += 1,
+                HIDE_COMPLETED => {
+                    hide_completed = !hide_completed;
+                    page = 0;
+                }
+                _ => continue,
+            },
+            _ => continue,
         }
+        interaction
+            .create_response(
+                &ctx,
+                CreateInteractionResponse::UpdateMessage(message(page, hide_completed)?),
+            )
+            .await?;
     }
 
     Ok(())
 }
 
-async fn show_archived_tasks(interaction: ComponentInteraction, ctx: Context) -> Result<(), Error> {
+async fn show_archived_tasks(
+    interaction: ComponentInteraction,
+    ctx: Context,
+    data: Data,
+) -> Result<(), Error> {
     const PREV: &str = "prev";
     const NEXT: &str = "next";
 
+    let guild_id = interaction.guild_id.context("Not in a guild")?;
     let mut page = 0;
     let message = |page: usize| -> Result<_, Error> {
-        let tasks = data::load()?.tasks.lock().unwrap().clone();
+        let now = Local::now();
+        let guild_data = data.guild(guild_id);
+        let tasks = guild_data
+            .tasks_in(..now, None)
+            .into_iter()
+            // 今日の終日のタスクや期間中のタスクはまだ終わっていない
+            .filter(|task| task.end_datetime() <= now)
+            .collect::<Vec<_>>();
         let fields = tasks
             .iter()
-            .filter(|e| Local::now() > e.datetime)
-            .sorted_by_key(|e| e.datetime)
             .rev()
-            .map(|task| task.to_field())
+            .map(|task| guild_data.task_field(task))
             .skip(TASKS_PER_PAGE * page);
 
         Ok(CreateInteractionResponseMessage::new()
@@ -252,6 +370,7 @@ async fn show_archived_tasks(interaction: ComponentInteraction, ctx: Context) ->
 
     log(
         &ctx,
+        &data.guild(guild_id),
         &interaction.user,
         format!(
             "{}さんが過去のタスク一覧を確認しました",
@@ -6,15 +6,16 @@ use poise::serenity_prelude::*;
 
 use crate::{data, PoiseContext};
 
-#[poise::command(slash_command)]
+#[poise::command(slash_command, guild_only)]
 /// タスク通知を送るチャンネルを設定します。
 pub async fn set_ping_channel(ctx: PoiseContext<'_>) -> Result<(), Error> {
-    ctx.data()
+    let (guild_id, guild_data) = data::of(ctx)?;
+    guild_data
         .ping_channel
         .lock()
         .unwrap()
         .replace(ctx.channel_id());
-    data::save(ctx.data())?;
+    ctx.data().request_save(guild_id);
 
     ctx.send(
         poise::CreateReply::default().embed(
@@ -29,9 +30,10 @@ pub async fn set_ping_channel(ctx: PoiseContext<'_>) -> Result<(), Error> {
     Ok(())
 }
 
-#[poise::command(slash_command)]
+#[poise::command(slash_command, guild_only)]
 /// タスク通知を送るロールを設定します。
 pub async fn set_ping_role(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let (guild_id, guild_data) = data::of(ctx)?;
     const ROLE: &str = "role";
     const SUBMIT: &str = "submit";
 
@@ -87,21 +89,19 @@ pub async fn set_ping_role(ctx: PoiseContext<'_>) -> Result<(), Error> {
                 );
                 interaction.create_response(ctx, response).await?;
             }
-            ComponentInteractionDataKind::Button => {
-                if interaction.data.custom_id == SUBMIT {
-                    last_interaction.replace(interaction);
-                    break;
-                }
+            ComponentInteractionDataKind::Button if interaction.data.custom_id == SUBMIT => {
+                last_interaction.replace(interaction);
+                break;
             }
             _ => {}
         }
     }
-    ctx.data()
+    guild_data
         .ping_role
         .lock()
         .unwrap()
         .replace(select.context("No role selected")?);
-    data::save(ctx.data())?;
+    ctx.data().request_save(guild_id);
 
     let response = CreateInteractionResponse::UpdateMessage(
         CreateInteractionResponseMessage::default()
@@ -0,0 +1,183 @@
+use std::time::Duration;
+
+use anyhow::{Context as _, Error};
+use chrono::Local;
+use poise::serenity_prelude::*;
+
+use crate::{
+    commands::undo::{listen_undo, undo_button},
+    data,
+    interactions::create_task,
+    natural_datetime::parse_natural_datetime,
+    utilities::format_date,
+    PartialTask, PoiseContext, Subject,
+};
+
+#[poise::command(slash_command, guild_only)]
+/// 「明日 数学 ワーク」のような文章からタスクを追加します。
+pub async fn quick_add(
+    ctx: PoiseContext<'_>,
+    #[description = "日付・時刻・教科・カテゴリー・詳細を空白で区切って入力(例: 来週の金曜 1限 数学 小テスト)"]
+    text: String,
+) -> Result<(), Error> {
+    const SUBMIT: &str = "submit";
+    const EDIT: &str = "edit";
+    const CANCEL: &str = "cancel";
+
+    let (guild_id, guild_data) = data::of(ctx)?;
+    let suggest_times = guild_data.suggest_times.lock().unwrap().clone();
+    let parsed = parse_natural_datetime(&text, Local::now().date_naive(), &suggest_times);
+
+    // 日付と時刻以外の語から、最初に一致した教科とカテゴリーを使い、残りを詳細にする
+    let mut subject = None;
+    let mut category = None;
+    let mut details = vec![];
+    for word in parsed.rest {
+        if subject.is_none() {
+            if let Some(name) = guild_data.match_subject(&word) {
+                subject = Some(Subject::Set(name));
+                continue;
+            }
+        }
+        if category.is_none() {
+            if let Some(c) = guild_data.match_category(&word) {
+                category = Some(c);
+                continue;
+            }
+        }
+        details.push(word);
+    }
+
+    // 時刻がない場合は終日のタスクにする
+    // 詳細がない場合は「修正する」から入力させる
+    let task = PartialTask {
+        category: category.or_else(|| guild_data.fallback_category()),
+        subject: Some(subject.unwrap_or(Subject::Unset)),
+        details: Some(details.join(" ")).filter(|d| !d.is_empty()),
+        date: parsed.date,
+        time: parsed.time,
+        all_day: parsed.date.is_some() && parsed.time.is_none(),
+        ..Default::default()
+    };
+
+    let not_set = || "未指定".to_string();
+    let embed = CreateEmbed::default()
+        .title("この内容でタスクを追加しますか？")
+        .description(text.clone())
+        .fields(vec![
+            (
+                "カテゴリー",
+                task.category
+                    .as_ref()
+                    .map_or_else(not_set, |c| guild_data.category_info(c).label()),
+                true,
+            ),
+            (
+                "教科",
+                match &task.subject {
+                    Some(Subject::Set(name)) => guild_data
+                        .subject_info(name)
+                        .map_or(name.clone(), |s| s.label()),
+                    _ => "(教科を指定しない)".into(),
+                },
+                true,
+            ),
+            ("日付", task.date.map_or_else(not_set, format_date), true),
+            (
+                "時刻",
+                match task.time {
+                    _ if task.all_day => "終日".into(),
+                    Some(time) => time.format("%H:%M").to_string(),
+                    None => not_set(),
+                },
+                true,
+            ),
+            ("詳細", task.details.clone().unwrap_or_else(not_set), false),
+        ])
+        .footer(CreateEmbedFooter::new(
+            "足りない項目や間違いは「修正する」から選び直せます",
+        ))
+        .color(Color::DARK_BLUE);
+
+    let message = ctx
+        .send(poise::CreateReply::default().embed(embed).components(vec![
+            CreateActionRow::Buttons(vec![
+                    CreateButton::new(SUBMIT)
+                        .label("追加する")
+                        .style(ButtonStyle::Primary)
+                        .disabled(task.unpartial().is_err()),
+                    CreateButton::new(EDIT)
+                        .label("修正する")
+                        .style(ButtonStyle::Secondary),
+                    CreateButton::new(CANCEL)
+                        .label("キャンセル")
+                        .style(ButtonStyle::Secondary),
+                ]),
+        ]))
+        .await?
+        .into_message()
+        .await?;
+
+    let interaction = message
+        .await_component_interaction(ctx)
+        .author_id(ctx.author().id)
+        .timeout(Duration::from_secs(60 * 30))
+        .await
+        .context("No interaction")?;
+
+    let added = |task| {
+        CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::default()
+                .embed(
+                    CreateEmbed::default()
+                        .title("タスクを追加しました")
+                        .fields(vec![guild_data.task_field(task)])
+                        .color(Color::DARK_GREEN),
+                )
+                .components(vec![CreateActionRow::Buttons(vec![undo_button()])]),
+        )
+    };
+
+    let (message, event) = match interaction.data.custom_id.as_str() {
+        SUBMIT => {
+            let task = task.unpartial()?;
+            let event = guild_data.add_task(ctx.author().id, task.clone());
+            ctx.data().request_save(guild_id);
+            interaction.create_response(ctx, added(&task)).await?;
+            (interaction.get_response(ctx).await?, event)
+        }
+        EDIT => {
+            let (last_interaction, task) = create_task(
+                ctx,
+                Some(interaction),
+                Some(
+                    CreateEmbed::default()
+                        .title("タスクを追加します")
+                        .color(Color::DARK_BLUE),
+                ),
+                task,
+            )
+            .await?;
+            let event = guild_data.add_task(ctx.author().id, task.clone());
+            ctx.data().request_save(guild_id);
+            last_interaction.create_response(ctx, added(&task)).await?;
+            (last_interaction.get_response(ctx).await?, event)
+        }
+        _ => {
+            let response = CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::default()
+                    .embed(
+                        CreateEmbed::default()
+                            .title("追加をキャンセルしました")
+                            .color(Color::DARK_GREY),
+                    )
+                    .components(vec![]),
+            );
+            interaction.create_response(ctx, response).await?;
+            return Ok(());
+        }
+    };
+    listen_undo(ctx, message, event.id).await?;
+
+    Ok(())
+}
@@ -0,0 +1,109 @@
+use anyhow::Error;
+use chrono::Datelike;
+use poise::serenity_prelude::*;
+
+use crate::{
+    commands::undo::{listen_undo, undo_button},
+    data,
+    interactions::select_task,
+    recurrence::{parse_weekdays, Recurrence, RecurrenceEnd, RecurrenceRule},
+    utilities::parse_date,
+    PoiseContext, Task,
+};
+
+#[derive(poise::ChoiceParameter)]
+pub enum Frequency {
+    #[name = "毎週"]
+    Weekly,
+    #[name = "N日ごと"]
+    Daily,
+    #[name = "毎月"]
+    Monthly,
+    #[name = "繰り返さない"]
+    Never,
+}
+
+#[poise::command(slash_command, guild_only)]
+/// タスクを繰り返すように設定します。
+pub async fn set_recurrence(
+    ctx: PoiseContext<'_>,
+    #[description = "繰り返し方"] frequency: Frequency,
+    #[description = "毎週の場合の曜日(例: 月,水,金) / 省略するとタスクの曜日"] weekdays: Option<
+        String,
+    >,
+    #[description = "N日ごとの場合の日数"]
+    #[min = 1]
+    #[max = 365]
+    interval: Option<u32>,
+    #[description = "この日まで繰り返す(例: 2024/07/31)"] until: Option<String>,
+    #[description = "繰り返す回数(最初の回を含む)"]
+    #[min = 1]
+    #[max = 1000]
+    count: Option<u32>,
+) -> Result<(), Error> {
+    let (guild_id, guild_data) = data::of(ctx)?;
+
+    // タスクを選ぶ前に入力を確かめておく
+    let end = match (&frequency, until, count) {
+        (Frequency::Never, _, _) => None,
+        (_, Some(until), None) => Some(RecurrenceEnd::Until(parse_date(&until)?)),
+        (_, None, Some(count)) => Some(RecurrenceEnd::Count(count)),
+        _ => anyhow::bail!("終わりの日か回数のどちらか一方を指定してください"),
+    };
+    let weekdays = weekdays.as_deref().map(parse_weekdays).transpose()?;
+
+    let (last_interaction, task) = select_task(
+        ctx,
+        None,
+        Some(
+            CreateEmbed::default()
+                .title("繰り返すタスクを選択")
+                .color(Color::DARK_BLUE),
+        ),
+    )
+    .await?;
+
+    let date = task.datetime.date_naive();
+    let recurrence = end.map(|end| Recurrence {
+        rule: match frequency {
+            Frequency::Weekly => {
+                RecurrenceRule::Weekly(weekdays.unwrap_or_else(|| vec![date.weekday()]))
+            }
+            Frequency::Daily => RecurrenceRule::Daily(interval.unwrap_or(1)),
+            Frequency::Monthly | Frequency::Never => RecurrenceRule::Monthly(date.day()),
+        },
+        end,
+        exceptions: Default::default(),
+    });
+    let modified_task = Task {
+        recurrence,
+        ..task.clone()
+    };
+
+    let event = guild_data.edit_task(ctx.author().id, modified_task.clone())?;
+    ctx.data().request_save(guild_id);
+
+    let response = CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::default()
+            .embed(
+                CreateEmbed::default()
+                    .title(match modified_task.recurrence {
+                        Some(_) => "繰り返しを設定しました",
+                        None => "繰り返しを解除しました",
+                    })
+                    .fields(vec![
+                        guild_data.task_field(&task),
+                        ("↓".into(), "".into(), false),
+                        guild_data.task_field(&modified_task),
+                    ])
+                    .color(Color::DARK_GREEN),
+            )
+            .components(vec![CreateActionRow::Buttons(vec![undo_button()])]),
+    );
+    last_interaction.create_response(ctx, response).await?;
+
+    let message = last_interaction.get_response(ctx).await?;
+    listen_undo(ctx, message, event.id).await?;
+
+    Ok(())
+}
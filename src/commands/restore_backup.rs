@@ -0,0 +1,412 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
+
+use anyhow::{Context as _, Error};
+use chrono::Local;
+use futures::StreamExt;
+use poise::serenity_prelude::*;
+
+use crate::{
+    commands::panel::listen_panel_interactions,
+    data::{self, GuildData},
+    storage::migration,
+    utilities::format_datetime,
+    PoiseContext,
+};
+
+// 差分の表示が埋め込みのフィールドの文字数制限(1024文字)を超えないようにする
+const MAX_DIFF_LINES: usize = 10;
+const MAX_DIFF_LINE_CHARS: usize = 80;
+const RECENT_MESSAGES: u8 = 50;
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    default_member_permissions = "ADMINISTRATOR"
+)]
+/// バックアップからデータを復元します。
+pub async fn restore_backup(
+    ctx: PoiseContext<'_>,
+    #[description = "復元するバックアップファイル / 省略するとログチャンネルから選択できます"]
+    file: Option<Attachment>,
+) -> Result<(), Error> {
+    const CONFIRM: &str = "confirm";
+    const CANCEL: &str = "cancel";
+
+    let (guild_id, guild_data) = data::of(ctx)?;
+
+    let (interaction, backup) = match file {
+        Some(file) => (None, file),
+        None => {
+            let (interaction, file) = select_backup(ctx, &guild_data).await?;
+            (Some(interaction), file)
+        }
+    };
+
+    let restored = backup
+        .download()
+        .await
+        .map_err(Error::from)
+        .and_then(|bytes| Ok(serde_json::from_slice(&bytes)?))
+        .and_then(migration::from_value)
+        .with_context(|| format!("{}は有効なバックアップではありません", backup.filename))?;
+
+    let embed = CreateEmbed::default()
+        .title("このバックアップから復元しますか？")
+        .description(backup.filename.clone())
+        .fields(diff_fields(&guild_data, &restored))
+        .color(Color::DARK_BLUE);
+    let components = vec![CreateActionRow::Buttons(vec![
+        CreateButton::new(CONFIRM)
+            .label("復元する")
+            .style(ButtonStyle::Danger),
+        CreateButton::new(CANCEL)
+            .label("キャンセル")
+            .style(ButtonStyle::Secondary),
+    ])];
+
+    let message = if let Some(interaction) = interaction {
+        let response = CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::default()
+                .embed(embed)
+                .components(components),
+        );
+        interaction.create_response(ctx, response).await?;
+        interaction.get_response(ctx).await?
+    } else {
+        ctx.send(
+            poise::CreateReply::default()
+                .embed(embed)
+                .components(components)
+                .ephemeral(true),
+        )
+        .await?
+        .into_message()
+        .await?
+    };
+
+    let interaction = message
+        .await_component_interaction(ctx)
+        .author_id(ctx.author().id)
+        .timeout(Duration::from_secs(60 * 30))
+        .await
+        .context("No interaction")?;
+
+    if interaction.data.custom_id != CONFIRM {
+        let response = CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::default()
+                .embed(
+                    CreateEmbed::default()
+                        .title("復元をキャンセルしました")
+                        .color(Color::DARK_GREY),
+                )
+                .components(vec![]),
+        );
+        interaction.create_response(ctx, response).await?;
+        return Ok(());
+    }
+
+    // Webhookと購読用URLはバックアップに含めないので、今の設定を残す
+    *restored.webhooks.lock().unwrap() = guild_data.webhooks.lock().unwrap().clone();
+    *restored.calendar_feeds.lock().unwrap() = guild_data.calendar_feeds.lock().unwrap().clone();
+    guild_data.replace_with(restored);
+    let id_pair = *guild_data.panel_message.lock().unwrap();
+    // 復元したパネルのメッセージで待ち受けを作り直す
+    {
+        let mut panel_listener = guild_data.panel_listener.lock().unwrap();
+        if let Some(handle) = panel_listener.take() {
+            handle.abort();
+        }
+        if let Some(id_pair) = id_pair {
+            panel_listener.replace(tokio::spawn(listen_panel_interactions(
+                ctx.serenity_context().clone(),
+                ctx.data().clone(),
+                id_pair,
+            )));
+        }
+    }
+    ctx.data().request_save(guild_id);
+
+    let response = CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::default()
+            .embed(
+                CreateEmbed::default()
+                    .title("バックアップから復元しました")
+                    .description(backup.filename)
+                    .color(Color::DARK_GREEN),
+            )
+            .components(vec![]),
+    );
+    interaction.create_response(ctx, response).await?;
+
+    Ok(())
+}
+
+/// ログチャンネルに投稿された最近のバックアップから選択させます。
+async fn select_backup(
+    ctx: PoiseContext<'_>,
+    guild_data: &GuildData,
+) -> Result<(ComponentInteraction, Attachment), Error> {
+    const BACKUP: &str = "backup";
+    const SUBMIT: &str = "submit";
+
+    let log_channel = (*guild_data.log_channel.lock().unwrap()).context("Log channel not set")?;
+    let backups = log_channel
+        .messages(ctx, GetMessages::new().limit(RECENT_MESSAGES))
+        .await?
+        .into_iter()
+        .filter(|m| m.author.id == ctx.framework().bot_id)
+        .flat_map(|m| {
+            let timestamp = m.timestamp.with_timezone(&Local);
+            m.attachments
+                .into_iter()
+                .filter(|a| a.filename.ends_with(".json"))
+                .map(move |a| (timestamp, a))
+        })
+        .take(25)
+        .collect::<Vec<_>>();
+    anyhow::ensure!(!backups.is_empty(), "No backups found in log channel");
+
+    let components = |selected: Option<usize>| {
+        let options = CreateSelectMenuKind::String {
+            options: backups
+                .iter()
+                .enumerate()
+                .map(|(i, (timestamp, attachment))| {
+                    CreateSelectMenuOption::new(format_datetime(*timestamp), i.to_string())
+                        .description(attachment.filename.clone())
+                        .default_selection(selected == Some(i))
+                })
+                .collect(),
+        };
+
+        vec![
+            CreateActionRow::SelectMenu(
+                CreateSelectMenu::new(BACKUP, options).placeholder("バックアップ"),
+            ),
+            CreateActionRow::Buttons(vec![CreateButton::new(SUBMIT)
+                .style(ButtonStyle::Primary)
+                .label("送信")
+                .disabled(selected.is_none())]),
+        ]
+    };
+
+    let mut select = None;
+
+    let message = ctx
+        .send(
+            poise::CreateReply::default()
+                .embed(
+                    CreateEmbed::default()
+                        .title("復元するバックアップを選択してください")
+                        .color(Color::DARK_BLUE),
+                )
+                .components(components(select))
+                .ephemeral(true),
+        )
+        .await?;
+
+    let mut interaction_stream = message
+        .clone()
+        .into_message()
+        .await?
+        .await_component_interaction(ctx)
+        .author_id(ctx.author().id)
+        .timeout(Duration::from_secs(60 * 30))
+        .stream();
+
+    let mut last_interaction = None;
+    while let Some(interaction) = interaction_stream.next().await {
+        match &interaction.data.kind {
+            ComponentInteractionDataKind::StringSelect { values } => {
+                if interaction.data.custom_id == BACKUP {
+                    select.replace(values[0].parse::<usize>()?);
+                }
+                let response = CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::default().components(components(select)),
+                );
+                interaction.create_response(ctx, response).await?;
+            }
+            ComponentInteractionDataKind::Button if interaction.data.custom_id == SUBMIT => {
+                last_interaction.replace(interaction);
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    let (_, attachment) = backups
+        .into_iter()
+        .nth(select.context("Backup not selected")?)
+        .context("Invalid backup")?;
+
+    Ok((last_interaction.context("No interaction")?, attachment))
+}
+
+/// 現在のデータと復元するデータの差分を埋め込みのフィールドにまとめます。
+fn diff_fields(current: &GuildData, restored: &GuildData) -> Vec<(String, String, bool)> {
+    let current_tasks = current
+        .tasks
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|t| (t.id, t.clone()))
+        .collect::<BTreeMap<_, _>>();
+    let restored_tasks = restored
+        .tasks
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|t| (t.id, t.clone()))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut task_diff = vec![];
+    for (id, task) in &restored_tasks {
+        match current_tasks.get(id) {
+            None => task_diff.push(format!("+ {}", task.to_field().0)),
+            Some(current) if current != task => task_diff.push(format!("~ {}", task.to_field().0)),
+            Some(_) => {}
+        }
+    }
+    for (id, task) in &current_tasks {
+        if !restored_tasks.contains_key(id) {
+            task_diff.push(format!("- {}", task.to_field().0));
+        }
+    }
+
+    let subject_names = |data: &GuildData| {
+        data.subjects
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|s| s.name.clone())
+            .collect::<BTreeSet<_>>()
+    };
+    let current_subjects = subject_names(current);
+    let restored_subjects = subject_names(restored);
+    let subject_diff = restored_subjects
+        .difference(&current_subjects)
+        .map(|s| format!("+ {}", s))
+        .chain(
+            current_subjects
+                .difference(&restored_subjects)
+                .map(|s| format!("- {}", s)),
+        )
+        .collect::<Vec<_>>();
+
+    let current_times = current.suggest_times.lock().unwrap().clone();
+    let restored_times = restored.suggest_times.lock().unwrap().clone();
+    let mut settings_diff = vec![];
+    if current_times != restored_times {
+        settings_diff.push(format!(
+            "~ よく使う時間: {}件 → {}件",
+            current_times.len(),
+            restored_times.len()
+        ));
+    }
+    let mut setting = |label: &str, current: Option<String>, restored: Option<String>| {
+        if current != restored {
+            settings_diff.push(format!(
+                "~ {}: {} → {}",
+                label,
+                current.unwrap_or("未設定".into()),
+                restored.unwrap_or("未設定".into())
+            ));
+        }
+    };
+    setting(
+        "通知チャンネル",
+        current
+            .ping_channel
+            .lock()
+            .unwrap()
+            .map(|c| c.mention().to_string()),
+        restored
+            .ping_channel
+            .lock()
+            .unwrap()
+            .map(|c| c.mention().to_string()),
+    );
+    setting(
+        "通知ロール",
+        current
+            .ping_role
+            .lock()
+            .unwrap()
+            .map(|r| r.mention().to_string()),
+        restored
+            .ping_role
+            .lock()
+            .unwrap()
+            .map(|r| r.mention().to_string()),
+    );
+    setting(
+        "ログチャンネル",
+        current
+            .log_channel
+            .lock()
+            .unwrap()
+            .map(|c| c.mention().to_string()),
+        restored
+            .log_channel
+            .lock()
+            .unwrap()
+            .map(|c| c.mention().to_string()),
+    );
+    setting(
+        "パネル",
+        current
+            .panel_message
+            .lock()
+            .unwrap()
+            .map(|(m, c)| m.link(c, None)),
+        restored
+            .panel_message
+            .lock()
+            .unwrap()
+            .map(|(m, c)| m.link(c, None)),
+    );
+
+    vec![
+        (
+            format!(
+                "タスク ({}件 → {}件)",
+                current_tasks.len(),
+                restored_tasks.len()
+            ),
+            format_diff(task_diff),
+            false,
+        ),
+        (
+            format!(
+                "教科 ({}件 → {}件)",
+                current_subjects.len(),
+                restored_subjects.len()
+            ),
+            format_diff(subject_diff),
+            false,
+        ),
+        ("設定".into(), format_diff(settings_diff), false),
+    ]
+}
+
+fn format_diff(lines: Vec<String>) -> String {
+    if lines.is_empty() {
+        return "変更なし".into();
+    }
+    let omitted = lines.len().saturating_sub(MAX_DIFF_LINES);
+    let mut lines = lines
+        .into_iter()
+        .take(MAX_DIFF_LINES)
+        .map(|line| match line.char_indices().nth(MAX_DIFF_LINE_CHARS) {
+            Some((index, _)) => format!("{}…", &line[..index]),
+            None => line,
+        })
+        .collect::<Vec<_>>();
+    if omitted > 0 {
+        lines.push(format!("…他{}件", omitted));
+    }
+    format!("```diff\n{}\n```", lines.join("\n"))
+}
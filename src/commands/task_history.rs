@@ -0,0 +1,174 @@
+use std::time::Duration;
+
+use anyhow::{Context as _, Error};
+use futures::StreamExt;
+use poise::serenity_prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    data::{self, TaskEvent},
+    interactions::select_task,
+    utilities::{format_datetime, truncate},
+    PoiseContext,
+};
+
+// 埋め込みに表示する履歴の最大数
+const MAX_EVENTS: usize = 10;
+
+#[poise::command(slash_command, guild_only)]
+/// タスクの変更履歴を確認します。管理者は以前の版に戻せます。
+pub async fn task_history(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    const REVISION: &str = "revision";
+    const REVERT: &str = "revert";
+
+    let (guild_id, guild_data) = data::of(ctx)?;
+    let (interaction, task) = select_task(
+        ctx,
+        None,
+        Some(
+            CreateEmbed::default()
+                .title("履歴を確認するタスクを選択")
+                .color(Color::DARK_BLUE),
+        ),
+    )
+    .await?;
+
+    let history = guild_data.task_history(task.id);
+    let revisions = history
+        .iter()
+        .rev()
+        .filter(|e| e.after.as_ref().is_some_and(|t| t != &task))
+        .take(25)
+        .collect::<Vec<_>>();
+
+    let embed = CreateEmbed::default()
+        .title("タスクの変更履歴")
+        .description(task.to_field().0)
+        .fields(history.iter().rev().take(MAX_EVENTS).map(event_field))
+        .footer(CreateEmbedFooter::new(format!("全{}件", history.len())))
+        .color(Color::DARK_BLUE);
+
+    let components = |selected: Option<Uuid>| {
+        let options = CreateSelectMenuKind::String {
+            options: revisions
+                .iter()
+                .map(|e| {
+                    // 選択肢の表示と説明は100文字まで
+                    CreateSelectMenuOption::new(
+                        truncate(
+                            &format!("{} {}", format_datetime(e.timestamp), e.label()),
+                            100,
+                        ),
+                        e.id.to_string(),
+                    )
+                    .description(truncate(&e.after.as_ref().unwrap().to_field().0, 100))
+                    .default_selection(selected == Some(e.id))
+                })
+                .collect(),
+        };
+
+        if revisions.is_empty() {
+            return vec![];
+        }
+        vec![
+            CreateActionRow::SelectMenu(
+                CreateSelectMenu::new(REVISION, options).placeholder("戻したい版 (管理者のみ)"),
+            ),
+            CreateActionRow::Buttons(vec![CreateButton::new(REVERT)
+                .style(ButtonStyle::Danger)
+                .label("この版に戻す")
+                .disabled(selected.is_none())]),
+        ]
+    };
+
+    let mut select = None;
+
+    let response = CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::default()
+            .embed(embed)
+            .components(components(select)),
+    );
+    interaction.create_response(ctx, response).await?;
+
+    let mut interaction_stream = interaction
+        .get_response(ctx)
+        .await?
+        .await_component_interaction(ctx)
+        .timeout(Duration::from_secs(60 * 30))
+        .stream();
+
+    while let Some(interaction) = interaction_stream.next().await {
+        let is_admin = interaction
+            .member
+            .as_ref()
+            .and_then(|m| m.permissions)
+            .is_some_and(|p| p.administrator());
+        if !is_admin {
+            let response = CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::default()
+                    .content("以前の版に戻せるのは管理者のみです")
+                    .ephemeral(true),
+            );
+            interaction.create_response(ctx, response).await?;
+            continue;
+        }
+
+        match &interaction.data.kind {
+            ComponentInteractionDataKind::StringSelect { values } => {
+                if interaction.data.custom_id == REVISION {
+                    select.replace(values[0].parse::<Uuid>()?);
+                }
+                let response = CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::default().components(components(select)),
+                );
+                interaction.create_response(ctx, response).await?;
+            }
+            ComponentInteractionDataKind::Button if interaction.data.custom_id == REVERT => {
+                let revision = revisions
+                    .iter()
+                    .find(|e| Some(e.id) == select)
+                    .and_then(|e| e.after.clone())
+                    .context("Revision not selected")?;
+
+                let event = guild_data.apply(interaction.user.id, task.id, Some(revision));
+                ctx.data().request_save(guild_id);
+
+                let response = CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::default()
+                        .embed(
+                            CreateEmbed::default()
+                                .title("以前の版に戻しました")
+                                .fields(
+                                    event
+                                        .before
+                                        .iter()
+                                        .map(|t| t.to_field())
+                                        .chain([("↓".into(), "".into(), false)])
+                                        .chain(event.after.iter().map(|t| t.to_field())),
+                                )
+                                .color(Color::DARK_GREEN),
+                        )
+                        .components(vec![]),
+                );
+                interaction.create_response(ctx, response).await?;
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn event_field(event: &TaskEvent) -> (String, String, bool) {
+    let task = event.after.as_ref().or(event.before.as_ref());
+    (
+        format!("{} {}", format_datetime(event.timestamp), event.label()),
+        format!(
+            "{}\n{}",
+            event.actor.mention(),
+            task.map(|t| t.to_field().0).unwrap_or_default()
+        ),
+        false,
+    )
+}
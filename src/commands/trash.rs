@@ -0,0 +1,253 @@
+use std::time::Duration;
+
+use anyhow::{Context as _, Error};
+use chrono::Local;
+use futures::StreamExt;
+use poise::serenity_prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    data,
+    utilities::{format_datetime, truncate},
+    PoiseContext,
+};
+
+// 埋め込みのフィールド名と選択肢の文字数制限
+const MAX_FIELD_NAME_CHARS: usize = 256;
+const MAX_OPTION_CHARS: usize = 100;
+
+#[poise::command(slash_command, guild_only)]
+/// ゴミ箱の中身を確認します。
+pub async fn trash(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let (_, guild_data) = data::of(ctx)?;
+
+    let trash = guild_data.trash.lock().unwrap().clone();
+    let retention = guild_data.trash_retention_days();
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("ゴミ箱")
+                .description(if trash.is_empty() {
+                    "空です".to_string()
+                } else {
+                    format!("削除から{}日後に完全に削除されます", retention)
+                })
+                .fields(trash.iter().rev().take(25).map(|e| {
+                    (
+                        truncate(&e.item.label(), MAX_FIELD_NAME_CHARS),
+                        format!(
+                            "{}に{}が削除",
+                            format_datetime(e.deleted_at),
+                            e.deleted_by.mention()
+                        ),
+                        false,
+                    )
+                }))
+                .footer(CreateEmbedFooter::new(format!("全{}件", trash.len())))
+                .color(Color::DARK_BLUE),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only)]
+/// ゴミ箱から元に戻します。
+pub async fn restore_trash(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    const ENTRY: &str = "entry";
+    const SUBMIT: &str = "submit";
+
+    let (guild_id, guild_data) = data::of(ctx)?;
+    let trash = guild_data.trash.lock().unwrap().clone();
+    anyhow::ensure!(!trash.is_empty(), "ゴミ箱は空です");
+
+    let components = |selected: Option<Uuid>| {
+        let options = CreateSelectMenuKind::String {
+            options: trash
+                .iter()
+                .rev()
+                .take(25)
+                .map(|e| {
+                    CreateSelectMenuOption::new(
+                        truncate(&e.item.label(), MAX_OPTION_CHARS),
+                        e.id.to_string(),
+                    )
+                    .description(format!("{}に削除", format_datetime(e.deleted_at)))
+                    .default_selection(selected == Some(e.id))
+                })
+                .collect(),
+        };
+
+        vec![
+            CreateActionRow::SelectMenu(
+                CreateSelectMenu::new(ENTRY, options).placeholder("元に戻す項目"),
+            ),
+            CreateActionRow::Buttons(vec![CreateButton::new(SUBMIT)
+                .style(ButtonStyle::Primary)
+                .label("送信")
+                .disabled(selected.is_none())]),
+        ]
+    };
+
+    let mut select = None;
+
+    let message = ctx
+        .send(
+            poise::CreateReply::default()
+                .embed(
+                    CreateEmbed::default()
+                        .title("元に戻す項目を選択してください")
+                        .color(Color::DARK_BLUE),
+                )
+                .components(components(select)),
+        )
+        .await?;
+
+    let mut interaction_stream = message
+        .clone()
+        .into_message()
+        .await?
+        .await_component_interaction(ctx)
+        .timeout(Duration::from_secs(60 * 30))
+        .stream();
+
+    let mut last_interaction = None;
+    while let Some(interaction) = interaction_stream.next().await {
+        match &interaction.data.kind {
+            ComponentInteractionDataKind::StringSelect { values } => {
+                if interaction.data.custom_id == ENTRY {
+                    select.replace(values[0].parse::<Uuid>()?);
+                }
+                let response = CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::default().components(components(select)),
+                );
+                interaction.create_response(ctx, response).await?;
+            }
+            ComponentInteractionDataKind::Button if interaction.data.custom_id == SUBMIT => {
+                last_interaction.replace(interaction);
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    let entry =
+        guild_data.restore_from_trash(ctx.author().id, select.context("Entry not selected")?)?;
+    ctx.data().request_save(guild_id);
+
+    let response = CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::default()
+            .embed(
+                CreateEmbed::default()
+                    .title("元に戻しました")
+                    .description(truncate(&entry.item.label(), MAX_OPTION_CHARS))
+                    .color(Color::DARK_GREEN),
+            )
+            .components(vec![]),
+    );
+
+    last_interaction
+        .context("No interaction")?
+        .create_response(ctx, response)
+        .await?;
+
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    default_member_permissions = "ADMINISTRATOR"
+)]
+/// ゴミ箱を空にします。
+pub async fn empty_trash(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    const CONFIRM: &str = "confirm";
+
+    let (guild_id, guild_data) = data::of(ctx)?;
+    let count = guild_data.trash.lock().unwrap().len();
+    anyhow::ensure!(count > 0, "ゴミ箱は空です");
+
+    let message = ctx
+        .send(
+            poise::CreateReply::default()
+                .embed(
+                    CreateEmbed::default()
+                        .title(format!("ゴミ箱の{}件を完全に削除しますか？", count))
+                        .description("この操作は取り消せません")
+                        .color(Color::DARK_RED),
+                )
+                .components(vec![CreateActionRow::Buttons(vec![CreateButton::new(
+                    CONFIRM,
+                )
+                .label("削除する")
+                .style(ButtonStyle::Danger)])]),
+        )
+        .await?;
+
+    let interaction = message
+        .into_message()
+        .await?
+        .await_component_interaction(ctx)
+        .author_id(ctx.author().id)
+        .timeout(Duration::from_secs(60 * 30))
+        .await
+        .context("No interaction")?;
+
+    let purged = guild_data.empty_trash();
+    ctx.data().request_save(guild_id);
+
+    let response = CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::default()
+            .embed(
+                CreateEmbed::default()
+                    .title(format!("ゴミ箱の{}件を削除しました", purged))
+                    .color(Color::DARK_GREEN),
+            )
+            .components(vec![]),
+    );
+    interaction.create_response(ctx, response).await?;
+
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    default_member_permissions = "ADMINISTRATOR"
+)]
+/// ゴミ箱の項目を完全に削除するまでの日数を設定します。
+pub async fn set_trash_retention(
+    ctx: PoiseContext<'_>,
+    #[description = "保持する日数"]
+    #[min = 1]
+    #[max = 365]
+    days: u32,
+) -> Result<(), Error> {
+    let (guild_id, guild_data) = data::of(ctx)?;
+
+    guild_data
+        .trash_retention_days
+        .lock()
+        .unwrap()
+        .replace(days);
+    let purged = guild_data.purge_trash(Local::now());
+    ctx.data().request_save(guild_id);
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title(format!("ゴミ箱の保持期間を{}日に設定しました", days))
+                .description(if purged > 0 {
+                    format!("期間を過ぎた{}件を削除しました", purged)
+                } else {
+                    "".into()
+                })
+                .color(Color::DARK_BLUE),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use anyhow::{Context as _, Error};
+use poise::serenity_prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    data::{self, TaskEvent},
+    PoiseContext, Task,
+};
+
+const UNDO: &str = "undo";
+// 変更してから取り消せる時間。結果のメッセージのボタンもこの時間で取り除く
+const UNDO_WINDOW: Duration = Duration::from_secs(60 * 10);
+
+#[poise::command(slash_command, guild_only)]
+/// 自分が最後に行ったタスクの変更を取り消します。
+pub async fn undo(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let (guild_id, guild_data) = data::of(ctx)?;
+
+    let event = guild_data
+        .last_undoable(ctx.author().id, UNDO_WINDOW)
+        .context("最近の取り消せる変更がありません")?;
+    let undone = guild_data.undo_batch(ctx.author().id, event.id)?;
+    ctx.data().request_save(guild_id);
+
+    // まとめて行った変更は、取り消しを選んだ最後の変更を結果として表示する
+    let undo = undone.first().context("No events")?;
+    ctx.send(poise::CreateReply::default().embed(undone_embed(&event, undo)))
+        .await?;
+
+    Ok(())
+}
+
+pub fn undo_button() -> CreateButton {
+    CreateButton::new(UNDO)
+        .label("元に戻す")
+        .style(ButtonStyle::Secondary)
+}
+
+/// 結果のメッセージの「元に戻す」ボタンが押されるのを待ち、変更を取り消します。
+/// まとめて行った変更は`/undo`と同じく`undo_batch`ですべて取り消し、`event_id`の変更を結果として表示します。
+/// 一定時間が過ぎるとボタンを取り除きます。
+pub async fn listen_undo(
+    ctx: PoiseContext<'_>,
+    mut message: Message,
+    event_id: Uuid,
+) -> Result<(), Error> {
+    let (guild_id, guild_data) = data::of(ctx)?;
+
+    let Some(interaction) = message
+        .await_component_interaction(ctx)
+        .author_id(ctx.author().id)
+        .custom_ids(vec![UNDO.into()])
+        .timeout(UNDO_WINDOW)
+        .await
+    else {
+        message
+            .edit(ctx, EditMessage::default().components(vec![]))
+            .await?;
+        return Ok(());
+    };
+
+    let event = guild_data
+        .history
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|e| e.id == event_id)
+        .cloned()
+        .context("Event not found")?;
+    let result = guild_data.undo_batch(interaction.user.id, event_id);
+    ctx.data().request_save(guild_id);
+    let response = match result.and_then(|undone| undone.into_iter().next().context("No events")) {
+        Ok(undo) => CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::default()
+                .embed(undone_embed(&event, &undo))
+                .components(vec![]),
+        ),
+        Err(e) => CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::default()
+                .embed(
+                    CreateEmbed::default()
+                        .title("元に戻せませんでした")
+                        .description(e.to_string())
+                        .color(Color::DARK_RED),
+                )
+                .components(vec![]),
+        ),
+    };
+    interaction.create_response(ctx, response).await?;
+
+    Ok(())
+}
+
+fn undone_embed(event: &TaskEvent, undo: &TaskEvent) -> CreateEmbed {
+    let field = |task: &Option<Task>| {
+        task.as_ref()
+            .map_or(("(なし)".into(), "".into(), false), |t| t.to_field())
+    };
+    CreateEmbed::default()
+        .title(format!("{}を取り消しました", event.label()))
+        .fields(vec![
+            field(&undo.before),
+            ("↓".into(), "".into(), false),
+            field(&undo.after),
+        ])
+        .color(Color::DARK_GREEN)
+}
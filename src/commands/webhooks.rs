@@ -0,0 +1,139 @@
+use anyhow::{Context as _, Error};
+use poise::serenity_prelude::*;
+
+use crate::{
+    data,
+    webhook::{self, Payload, WebhookEndpoint},
+    PoiseContext,
+};
+
+async fn autocomplete_webhook(ctx: PoiseContext<'_>, partial: &str) -> Vec<String> {
+    let Ok((_, guild_data)) = data::of(ctx) else {
+        return vec![];
+    };
+    let webhooks = guild_data.webhooks.lock().unwrap().clone();
+    webhooks
+        .into_iter()
+        .map(|w| w.url)
+        .filter(|url| url.contains(partial))
+        .take(25)
+        .collect()
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    ephemeral,
+    default_member_permissions = "ADMINISTRATOR"
+)]
+/// タスクが変更されたときに通知するWebhookを追加します。
+pub async fn add_webhook(
+    ctx: PoiseContext<'_>,
+    #[description = "通知を送るURL"] url: String,
+) -> Result<(), Error> {
+    let (guild_id, guild_data) = data::of(ctx)?;
+    anyhow::ensure!(
+        !guild_data
+            .webhooks
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|w| w.url == url),
+        "このURLは既に追加されています"
+    );
+
+    let endpoint = WebhookEndpoint::new(url, ctx.author().id)?;
+    guild_data.webhooks.lock().unwrap().push(endpoint.clone());
+    ctx.data().request_save(guild_id);
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("Webhookを追加しました")
+                .description(format!(
+                    "{}\n\n通知には `X-Webhook-Signature` ヘッダーとして、本文をこの鍵でHMAC-SHA256で署名した値が付きます\n```\n{}\n```",
+                    endpoint.url, endpoint.secret
+                ))
+                .color(Color::DARK_GREEN),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    ephemeral,
+    default_member_permissions = "ADMINISTRATOR"
+)]
+/// Webhookにテスト用の通知を送ります。
+pub async fn test_webhook(
+    ctx: PoiseContext<'_>,
+    #[description = "テストするURL"]
+    #[autocomplete = "autocomplete_webhook"]
+    url: String,
+) -> Result<(), Error> {
+    let (_, guild_data) = data::of(ctx)?;
+    let endpoint = guild_data
+        .webhooks
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|w| w.url == url)
+        .cloned()
+        .context("このURLは追加されていません")?;
+
+    // 再送を待つ間に応答期限が切れないようにする
+    ctx.defer_ephemeral().await?;
+    let body = serde_json::to_vec(&Payload::ping(ctx.author().id))?;
+    let embed = match webhook::deliver(&endpoint, &body).await {
+        Ok(()) => CreateEmbed::default()
+            .title("テスト用の通知を送りました")
+            .description(endpoint.url)
+            .color(Color::DARK_GREEN),
+        Err(e) => CreateEmbed::default()
+            .title("通知を送れませんでした")
+            .description(format!("{}\n{:#}", endpoint.url, e))
+            .color(Color::DARK_RED),
+    };
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    ephemeral,
+    default_member_permissions = "ADMINISTRATOR"
+)]
+/// Webhookを削除します。
+pub async fn remove_webhook(
+    ctx: PoiseContext<'_>,
+    #[description = "削除するURL"]
+    #[autocomplete = "autocomplete_webhook"]
+    url: String,
+) -> Result<(), Error> {
+    let (guild_id, guild_data) = data::of(ctx)?;
+    {
+        let mut webhooks = guild_data.webhooks.lock().unwrap();
+        let len = webhooks.len();
+        webhooks.retain(|w| w.url != url);
+        anyhow::ensure!(webhooks.len() < len, "このURLは追加されていません");
+    }
+    ctx.data().request_save(guild_id);
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("Webhookを削除しました")
+                .description(url)
+                .color(Color::DARK_GREEN),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -1,56 +1,101 @@
 use std::{
     collections::{BTreeMap, BTreeSet},
     fmt::Display,
-    fs,
-    sync::Mutex,
+    ops::RangeBounds,
+    path::Path,
+    sync::{Arc, Mutex},
 };
 
 use anyhow::{Context, Error};
 use chrono::{DateTime, Local, NaiveDate, NaiveTime, TimeZone};
 use poise::serenity_prelude::*;
 use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-pub enum Category {
-    // イベント
-    Event,
-    // テスト
-    Exam,
-    // 宿題
-    Homework,
-    // 持ち物
-    Belongings,
-    // その他
-    Other,
+use crate::{
+    recurrence::Recurrence,
+    storage,
+    task_store::TaskStore,
+    utilities::{format_date, format_datetime, parse_date},
+    webhook::{self, WebhookEndpoint},
+    PoiseContext,
+};
+
+/// タスクのカテゴリー。タスクにはカテゴリーの名前を記録する
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(transparent)]
+pub struct Category(pub String);
+
+impl Display for Category {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
 }
 
-impl From<Category> for String {
-    fn from(category: Category) -> Self {
-        match category {
-            Category::Event => "イベント",
-            Category::Exam => "テスト",
-            Category::Homework => "宿題",
-            Category::Belongings => "持ち物",
-            Category::Other => "その他",
+/// ギルドごとに設定するカテゴリーの表示
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CategoryInfo {
+    pub name: String,
+    pub emoji: Option<String>,
+    // 埋め込みの色
+    pub color: Option<u32>,
+}
+
+impl CategoryInfo {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.into(),
+            emoji: None,
+            color: None,
+        }
+    }
+
+    pub fn category(&self) -> Category {
+        Category(self.name.clone())
+    }
+
+    /// 絵文字があれば付けた名前を返します。
+    pub fn label(&self) -> String {
+        match &self.emoji {
+            Some(emoji) => format!("{} {}", emoji, self.name),
+            None => self.name.clone(),
         }
-        .to_string()
+    }
+
+    pub fn reaction(&self) -> Option<ReactionType> {
+        self.emoji.as_deref()?.parse().ok()
     }
 }
 
-impl Display for Category {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(&String::from(*self))
+/// カテゴリーの一覧。並び順がそのまま表示の順番になる
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Categories(pub Vec<CategoryInfo>);
+
+// カテゴリーを固定していたころの5つを、新しいギルドの初期値にする
+pub const DEFAULT_CATEGORIES: [&str; 5] = ["イベント", "テスト", "宿題", "持ち物", "その他"];
+// カテゴリーの名前は選択肢の値と表示に使うので、Discordの上限に合わせる
+const MAX_CATEGORY_NAME_CHARS: usize = 100;
+
+impl Default for Categories {
+    fn default() -> Self {
+        Self(DEFAULT_CATEGORIES.map(CategoryInfo::new).into())
+    }
+}
+
+impl std::ops::Deref for Categories {
+    type Target = Vec<CategoryInfo>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
     }
 }
 
-impl Category {
-    pub const VALUES: [Category; 5] = [
-        Category::Event,
-        Category::Exam,
-        Category::Homework,
-        Category::Belongings,
-        Category::Other,
-    ];
+impl std::ops::DerefMut for Categories {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -84,12 +129,66 @@ impl<'de> Deserialize<'de> for Subject {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+/// 登録されている教科の情報。タスクには教科の名前を記録する
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct SubjectInfo {
+    // 表示する名前
+    pub name: String,
+    pub teacher: Option<String>,
+    // 埋め込みの色
+    pub color: Option<u32>,
+    pub emoji: Option<String>,
+    // 「数I」のような短い名前
+    pub short_code: Option<String>,
+    // 検索や読み込みで、この教科とみなす別名
+    pub aliases: Vec<String>,
+}
+
+impl SubjectInfo {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    /// 絵文字があれば付けた名前を返します。
+    pub fn label(&self) -> String {
+        match &self.emoji {
+            Some(emoji) => format!("{} {}", emoji, self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    pub fn reaction(&self) -> Option<ReactionType> {
+        self.emoji.as_deref()?.parse().ok()
+    }
+
+    /// 名前・短い名前・別名のいずれかが一致するかどうか
+    pub fn matches(&self, name: &str) -> bool {
+        let name = name.trim().to_lowercase();
+        std::iter::once(&self.name)
+            .chain(&self.short_code)
+            .chain(&self.aliases)
+            .any(|n| n.to_lowercase() == name)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Task {
     pub category: Category,
     pub subject: Subject,
     pub details: String,
+    // 繰り返すタスクの場合は最初の回の日時。終日のタスクは0:00にする
     pub datetime: DateTime<Local>,
+    // 複数日にわたるタスクの終わりの日時。終日のタスクは最終日の0:00にする
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<DateTime<Local>>,
+    pub id: Uuid,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub all_day: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recurrence: Option<Recurrence>,
 }
 
 impl Task {
@@ -105,44 +204,183 @@ impl Task {
                 self.details
             ),
             format!(
-                "<t:{}:F>(<t:{}:R>)",
-                self.datetime.timestamp(),
-                self.datetime.timestamp()
+                "{}{}",
+                match (self.all_day, self.end) {
+                    (true, None) => format!("<t:{}:D>(終日)", self.datetime.timestamp()),
+                    (true, Some(end)) => format!(
+                        "<t:{}:D> 〜 <t:{}:D>(終日)",
+                        self.datetime.timestamp(),
+                        end.timestamp()
+                    ),
+                    (false, None) => format!(
+                        "<t:{}:F>(<t:{}:R>)",
+                        self.datetime.timestamp(),
+                        self.datetime.timestamp()
+                    ),
+                    (false, Some(end)) => format!(
+                        "<t:{}:F> 〜 <t:{}:F>(<t:{}:R>)",
+                        self.datetime.timestamp(),
+                        end.timestamp(),
+                        self.datetime.timestamp()
+                    ),
+                },
+                match &self.recurrence {
+                    Some(recurrence) => format!("\n🔁 {}", recurrence.label()),
+                    None => "".into(),
+                }
             ),
             false,
         )
     }
 
+    /// 繰り返すタスクは各回のタスクに分けて、日時順に返します。
+    pub fn occurrences(&self) -> Box<dyn Iterator<Item = Task> + '_> {
+        let Some(recurrence) = &self.recurrence else {
+            return Box::new(std::iter::once(self.clone()));
+        };
+        let time = self.datetime.time();
+        Box::new(
+            recurrence
+                .dates(self.datetime.date_naive())
+                .filter_map(move |date| Local.from_local_datetime(&date.and_time(time)).single())
+                .map(|datetime| Task {
+                    datetime,
+                    // 期間のあるタスクは、各回も同じ長さにする
+                    end: self.end.map(|end| datetime + (end - self.datetime)),
+                    ..self.clone()
+                }),
+        )
+    }
+
     pub fn as_partial(&self) -> PartialTask {
         self.clone().into()
     }
+
+    /// 日時を表示用の文字列にします。終日のタスクは日付のみです。
+    pub fn format_datetime(&self) -> String {
+        let format = |datetime: DateTime<Local>| {
+            if self.all_day {
+                format_date(datetime.date_naive())
+            } else {
+                format_datetime(datetime)
+            }
+        };
+        match self.end {
+            Some(end) => format!("{} 〜 {}", format(self.datetime), format(end)),
+            None => format(self.datetime),
+        }
+    }
+
+    /// タスクが終わる日時を返します。終日のタスクは最終日の翌日の0:00です。
+    pub fn end_datetime(&self) -> DateTime<Local> {
+        let end = self.end.unwrap_or(self.datetime);
+        if self.all_day {
+            end + chrono::Duration::days(1)
+        } else {
+            end
+        }
+    }
+
+    /// 繰り返すタスクの場合は、この回の日付を返します。
+    pub fn occurrence_date(&self) -> Option<NaiveDate> {
+        self.recurrence.as_ref().map(|_| self.datetime.date_naive())
+    }
+}
+
+/// 書き出しなどで使うタスクの絞り込み条件
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TaskFilter {
+    pub subject: Option<Subject>,
+    pub category: Option<Category>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+impl TaskFilter {
+    /// コマンドの引数として受け取った文字列から絞り込み条件を作ります。
+    pub fn parse(
+        subject: Option<String>,
+        category: Option<String>,
+        from: Option<String>,
+        to: Option<String>,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            subject: subject.map(Subject::Set),
+            category: category.map(Category),
+            from: from.as_deref().map(parse_date).transpose()?,
+            to: to.as_deref().map(parse_date).transpose()?,
+        })
+    }
+
+    pub fn matches(&self, task: &Task) -> bool {
+        // 複数日にわたるタスクは、期間と重なっていれば含める
+        let start = task.datetime.date_naive();
+        let end = task.end.unwrap_or(task.datetime).date_naive();
+        self.subject.as_ref().is_none_or(|s| &task.subject == s)
+            && self.category.as_ref().is_none_or(|c| &task.category == c)
+            && self.from.is_none_or(|from| from <= end)
+            && self.to.is_none_or(|to| start <= to)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct PartialTask {
+    pub id: Option<Uuid>,
     pub category: Option<Category>,
     pub subject: Option<Subject>,
     pub details: Option<String>,
     pub date: Option<NaiveDate>,
     pub time: Option<NaiveTime>,
+    pub all_day: bool,
+    // 終わりの日時を設定するかどうか
+    pub has_end: bool,
+    pub end_date: Option<NaiveDate>,
+    pub end_time: Option<NaiveTime>,
+    pub recurrence: Option<Recurrence>,
 }
 
 impl PartialTask {
     pub fn unpartial(&self) -> Result<Task, Error> {
-        let category = self.category.context("Category not selected")?;
+        let category = self.category.clone().context("Category not selected")?;
         let subject = self.subject.clone().context("Subject not selected")?;
         let details = self.details.clone().context("Details not selected")?;
         let date = self.date.context("Date not selected")?;
-        let time = self.time.context("Time not selected")?;
+        let time = match self.all_day {
+            true => NaiveTime::MIN,
+            false => self.time.context("Time not selected")?,
+        };
         let datetime = Local
             .from_local_datetime(&date.and_time(time))
             .single()
             .context("Invalid date and time")?;
+        let end = match self.has_end {
+            true => {
+                let end_date = self.end_date.context("End date not selected")?;
+                let end_time = match self.all_day {
+                    true => NaiveTime::MIN,
+                    false => self.end_time.context("End time not selected")?,
+                };
+                let end = Local
+                    .from_local_datetime(&end_date.and_time(end_time))
+                    .single()
+                    .context("Invalid end date and time")?;
+                anyhow::ensure!(
+                    datetime < end,
+                    "終わりの日時は始まりの日時より後にしてください"
+                );
+                Some(end)
+            }
+            false => None,
+        };
         Ok(Task {
             category,
             subject,
             details,
             datetime,
+            end,
+            id: self.id.unwrap_or_else(Uuid::new_v4),
+            all_day: self.all_day,
+            recurrence: self.recurrence.clone(),
         })
     }
 }
@@ -150,38 +388,1112 @@ impl PartialTask {
 impl From<Task> for PartialTask {
     fn from(task: Task) -> Self {
         Self {
+            id: Some(task.id),
             category: Some(task.category),
             subject: Some(task.subject),
             details: Some(task.details),
             date: Some(task.datetime.date_naive()),
-            time: Some(task.datetime.time()),
+            time: (!task.all_day).then(|| task.datetime.time()),
+            all_day: task.all_day,
+            has_end: task.end.is_some(),
+            end_date: task.end.map(|end| end.date_naive()),
+            end_time: task.end.filter(|_| !task.all_day).map(|end| end.time()),
+            recurrence: task.recurrence,
+        }
+    }
+}
+
+/// タスクに対する1回の変更の記録
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TaskEvent {
+    pub id: Uuid,
+    pub task_id: Uuid,
+    pub actor: UserId,
+    pub timestamp: DateTime<Local>,
+    pub before: Option<Task>,
+    pub after: Option<Task>,
+    // 取り消しによる変更の場合、取り消した変更のID
+    pub reverts: Option<Uuid>,
+    // 一度にまとめて行った変更に共通のID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch: Option<Uuid>,
+    // 教科やカテゴリーの名前の変更に合わせた書き換え。取り消せない
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub rename: bool,
+}
+
+impl TaskEvent {
+    pub fn label(&self) -> &'static str {
+        if self.reverts.is_some() {
+            return "取り消し";
+        }
+        if self.rename {
+            return "名前の変更";
+        }
+        match (&self.before, &self.after) {
+            (None, Some(_)) => "追加",
+            (Some(_), Some(_)) => "編集",
+            (Some(_), None) => "削除",
+            (None, None) => "不明",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum TrashItem {
+    Task(Task),
+    Subject(SubjectInfo),
+}
+
+impl TrashItem {
+    pub fn label(&self) -> String {
+        match self {
+            TrashItem::Task(task) => format!("[タスク] {}", task.to_field().0),
+            TrashItem::Subject(subject) => format!("[教科] {}", subject.name),
+        }
+    }
+}
+
+/// ゴミ箱に移動された項目
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TrashEntry {
+    pub id: Uuid,
+    pub item: TrashItem,
+    pub deleted_by: UserId,
+    pub deleted_at: DateTime<Local>,
+}
+
+pub const DEFAULT_TRASH_RETENTION_DAYS: u32 = 30;
+
+/// よく使う時間の上限。タスク作成の選択肢に「終日」「その他の時刻」と並べて25件に収める
+pub const MAX_SUGGEST_TIMES: usize = 23;
+
+/// カレンダーアプリから購読するためのURL。トークンを知っていれば誰でも読み取れる
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct CalendarFeed {
+    pub token: String,
+    // カレンダーアプリに表示される名前
+    pub name: String,
+    pub subject: Option<Subject>,
+    pub category: Option<Category>,
+    pub created_by: UserId,
+    pub created_at: DateTime<Local>,
+}
+
+// 起動時に表示する設定などにトークンが出ないよう、Debugでは伏せる
+impl std::fmt::Debug for CalendarFeed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CalendarFeed")
+            .field("token", &"<redacted>")
+            .field("name", &self.name)
+            .field("subject", &self.subject)
+            .field("category", &self.category)
+            .field("created_by", &self.created_by)
+            .field("created_at", &self.created_at)
+            .finish()
+    }
+}
+
+impl CalendarFeed {
+    pub fn filter(&self) -> TaskFilter {
+        TaskFilter {
+            subject: self.subject.clone(),
+            category: self.category.clone(),
+            ..Default::default()
+        }
+    }
+
+    pub fn label(&self) -> String {
+        let mut label = vec![];
+        if let Some(Subject::Set(subject)) = &self.subject {
+            label.push(subject.clone());
+        }
+        if let Some(category) = &self.category {
+            label.push(category.to_string());
+        }
+        if label.is_empty() {
+            "すべてのタスク".into()
+        } else {
+            label.join(" / ")
+        }
+    }
+}
+
+/// ユーザーがタスクを完了したことの記録
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Completion {
+    pub task_id: Uuid,
+    // 繰り返すタスクの場合は完了した回の日付
+    pub occurrence: Option<NaiveDate>,
+    pub user_id: UserId,
+}
+
+impl Completion {
+    pub fn new(task: &Task, user_id: UserId) -> Self {
+        Self {
+            task_id: task.id,
+            occurrence: task.occurrence_date(),
+            user_id,
         }
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, Default)]
-pub struct Data {
-    pub tasks: Mutex<BTreeSet<Task>>,
-    pub subjects: Mutex<BTreeSet<String>>,
+pub struct GuildData {
+    pub tasks: Mutex<TaskStore>,
+    // 名前順に並べておく
+    pub subjects: Mutex<Vec<SubjectInfo>>,
     pub suggest_times: Mutex<BTreeMap<NaiveTime, String>>,
     pub panel_message: Mutex<Option<(MessageId, ChannelId)>>,
     pub ping_channel: Mutex<Option<ChannelId>>,
     pub ping_role: Mutex<Option<RoleId>>,
     pub log_channel: Mutex<Option<ChannelId>>,
+    pub history: Mutex<Vec<TaskEvent>>,
+    pub trash: Mutex<Vec<TrashEntry>>,
+    // 未設定の場合は DEFAULT_TRASH_RETENTION_DAYS
+    pub trash_retention_days: Mutex<Option<u32>>,
+    pub calendar_feeds: Mutex<Vec<CalendarFeed>>,
+    pub webhooks: Mutex<Vec<WebhookEndpoint>>,
+    pub categories: Mutex<Categories>,
+    pub completions: Mutex<BTreeSet<Completion>>,
     #[serde(skip)]
     pub panel_listener: Mutex<Option<tokio::task::JoinHandle<Result<(), Error>>>>,
 }
 
-pub const FILE_PATH: &str = "data.json";
+impl GuildData {
+    pub fn task(&self, id: Uuid) -> Option<Task> {
+        self.tasks.lock().unwrap().get(id).cloned()
+    }
+
+    /// 期間と教科でタスクを絞り込み、日時順で返します。
+    pub fn tasks_in(
+        &self,
+        range: impl RangeBounds<DateTime<Local>>,
+        subject: Option<&Subject>,
+    ) -> Vec<Task> {
+        self.tasks.lock().unwrap().query(range, subject, None)
+    }
+
+    /// 絞り込み条件に合うタスクを日時順で返します。
+    pub fn filtered_tasks(&self, filter: &TaskFilter) -> Vec<Task> {
+        self.tasks
+            .lock()
+            .unwrap()
+            .query(.., filter.subject.as_ref(), filter.category.as_ref())
+            .into_iter()
+            .filter(|task| filter.matches(task))
+            .collect()
+    }
+
+    /// 登録されている教科から名前が一致するものを探します。
+    pub fn match_subject(&self, name: &str) -> Option<String> {
+        self.subjects
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|s| s.matches(name))
+            .map(|s| s.name.clone())
+    }
+
+    pub fn subject_info(&self, name: &str) -> Option<SubjectInfo> {
+        self.subjects
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|s| s.name == name)
+            .cloned()
+    }
+
+    /// 教科を追加します。同じ名前の教科がある場合は何もせず、falseを返します。
+    pub fn add_subject(&self, info: SubjectInfo) -> Result<bool, Error> {
+        let mut subjects = self.subjects.lock().unwrap();
+        let Err(index) = subjects.binary_search_by(|s| s.name.cmp(&info.name)) else {
+            return Ok(false);
+        };
+        ensure_distinct_names(
+            &subjects,
+            &info.name,
+            std::iter::once(&info.name)
+                .chain(&info.short_code)
+                .chain(&info.aliases),
+        )?;
+        subjects.insert(index, info);
+        Ok(true)
+    }
+
+    /// 教科の情報を変更します。名前は変更できません。
+    pub fn update_subject(&self, info: SubjectInfo) -> Result<(), Error> {
+        let mut subjects = self.subjects.lock().unwrap();
+        ensure_distinct_names(
+            &subjects,
+            &info.name,
+            info.short_code.iter().chain(&info.aliases),
+        )?;
+        let current = subjects
+            .iter_mut()
+            .find(|s| s.name == info.name)
+            .context("教科が見つかりません")?;
+        *current = info;
+        Ok(())
+    }
+
+    /// その教科のタスクの数を返します。
+    pub fn subject_task_count(&self, subject: &str) -> usize {
+        let subject = Subject::Set(subject.to_string());
+        self.tasks
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|task| task.subject == subject)
+            .count()
+    }
+
+    /// 教科の名前を変更し、その教科のタスクを書き換えます。書き換えたタスクの数を返します。
+    pub fn rename_subject(&self, actor: UserId, subject: &str, name: &str) -> Result<usize, Error> {
+        let name = name.trim();
+        anyhow::ensure!(!name.is_empty(), "名前を入力してください");
+        anyhow::ensure!(name != subject, "今の名前と同じです");
+        // 教科とタスクを同時に書き換えるため、終わるまで教科のロックを持っておく
+        let mut subjects = self.subjects.lock().unwrap();
+        if let Some(other) = subjects
+            .iter()
+            .find(|s| s.name != subject && s.matches(name))
+        {
+            anyhow::bail!("「{}」は{}の名前と重なっています", name, other.name);
+        }
+        let index = subjects
+            .iter()
+            .position(|s| s.name == subject)
+            .context("教科が見つかりません")?;
+        let mut info = subjects.remove(index);
+        info.name = name.to_string();
+        let index = subjects
+            .binary_search_by(|s| s.name.cmp(&info.name))
+            .unwrap_or_else(|index| index);
+        subjects.insert(index, info);
+        Ok(self.replace_subject(actor, subject, &Subject::Set(name.to_string())))
+    }
 
-pub fn save(data: &Data) -> Result<(), Error> {
-    let data = serde_json::to_string(data)?;
-    fs::write(FILE_PATH, data)?;
+    /// `from`の教科を`into`に統合し、そのタスクを書き換えます。書き換えたタスクの数を返します。
+    /// `from`の名前・略称・別名は`into`の別名になります。
+    pub fn merge_subject(&self, actor: UserId, from: &str, into: &str) -> Result<usize, Error> {
+        anyhow::ensure!(from != into, "同じ教科には統合できません");
+        let mut subjects = self.subjects.lock().unwrap();
+        let from_index = subjects
+            .iter()
+            .position(|s| s.name == from)
+            .context("統合する教科が見つかりません")?;
+        anyhow::ensure!(
+            subjects.iter().any(|s| s.name == into),
+            "統合先の教科が見つかりません"
+        );
+        let merged = subjects.remove(from_index);
+        let info = subjects
+            .iter_mut()
+            .find(|s| s.name == into)
+            .context("統合先の教科が見つかりません")?;
+        for alias in std::iter::once(merged.name)
+            .chain(merged.short_code)
+            .chain(merged.aliases)
+        {
+            if !info.matches(&alias) {
+                info.aliases.push(alias);
+            }
+        }
+        info.teacher = info.teacher.take().or(merged.teacher);
+        info.color = info.color.or(merged.color);
+        info.emoji = info.emoji.take().or(merged.emoji);
+        Ok(self.replace_subject(actor, from, &Subject::Set(into.to_string())))
+    }
+
+    /// 登録されているカテゴリーから名前が一致するものを探します。
+    pub fn match_category(&self, name: &str) -> Option<Category> {
+        let name = name.trim();
+        self.categories
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|c| c.name.to_lowercase() == name.to_lowercase())
+            .map(CategoryInfo::category)
+    }
+
+    /// カテゴリーを判別できないときに使うカテゴリーです。「その他」がなければ並び順で最後のものを使います。
+    pub fn fallback_category(&self) -> Option<Category> {
+        self.match_category("その他").or_else(|| {
+            self.categories
+                .lock()
+                .unwrap()
+                .last()
+                .map(CategoryInfo::category)
+        })
+    }
+
+    /// カテゴリーの表示を返します。登録されていないカテゴリーは名前のみです。
+    pub fn category_info(&self, category: &Category) -> CategoryInfo {
+        self.categories
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|c| c.name == category.0)
+            .cloned()
+            .unwrap_or_else(|| CategoryInfo::new(&category.0))
+    }
+
+    fn task_subject_info(&self, task: &Task) -> Option<SubjectInfo> {
+        match &task.subject {
+            Subject::Set(subject) => self.subject_info(subject),
+            Subject::Unset => None,
+        }
+    }
+
+    /// タスクを埋め込みのフィールドにします。カテゴリーと教科に絵文字があれば先頭に付けます。
+    pub fn task_field(&self, task: &Task) -> (String, String, bool) {
+        let (name, value, inline) = task.to_field();
+        let emojis = self
+            .category_info(&task.category)
+            .emoji
+            .into_iter()
+            .chain(self.task_subject_info(task).and_then(|s| s.emoji))
+            .collect::<Vec<_>>();
+        if emojis.is_empty() {
+            (name, value, inline)
+        } else {
+            (format!("{} {}", emojis.join(""), name), value, inline)
+        }
+    }
+
+    /// タスクを表示する色です。教科の色、カテゴリーの色の順に使います。
+    pub fn task_color(&self, task: &Task) -> Option<Color> {
+        self.task_subject_info(task)
+            .and_then(|s| s.color)
+            .or(self.category_info(&task.category).color)
+            .map(Color::new)
+    }
+
+    pub fn add_category(&self, info: CategoryInfo) -> Result<(), Error> {
+        anyhow::ensure!(
+            info.name.chars().count() <= MAX_CATEGORY_NAME_CHARS,
+            "名前は{}文字以内にしてください",
+            MAX_CATEGORY_NAME_CHARS
+        );
+        anyhow::ensure!(
+            self.match_category(&info.name).is_none(),
+            "同じ名前のカテゴリーがあります: {}",
+            info.name
+        );
+        self.categories.lock().unwrap().push(info);
+        Ok(())
+    }
+
+    /// カテゴリーの表示を変更します。名前が変わる場合はタスクのカテゴリーも書き換え、その数を返します。
+    pub fn edit_category(
+        &self,
+        actor: UserId,
+        category: &Category,
+        info: CategoryInfo,
+    ) -> Result<usize, Error> {
+        if info.name != category.0 {
+            anyhow::ensure!(
+                info.name.chars().count() <= MAX_CATEGORY_NAME_CHARS,
+                "名前は{}文字以内にしてください",
+                MAX_CATEGORY_NAME_CHARS
+            );
+            anyhow::ensure!(
+                self.match_category(&info.name).is_none(),
+                "同じ名前のカテゴリーがあります: {}",
+                info.name
+            );
+        }
+        {
+            let mut categories = self.categories.lock().unwrap();
+            let current = categories
+                .iter_mut()
+                .find(|c| c.name == category.0)
+                .context("カテゴリーが見つかりません")?;
+            *current = info.clone();
+        }
+        Ok(self.replace_category(actor, category, &info.category()))
+    }
+
+    /// カテゴリーを並び順の`position`番目(0から)に移動します。
+    pub fn move_category(&self, category: &Category, position: usize) -> Result<(), Error> {
+        let mut categories = self.categories.lock().unwrap();
+        let index = categories
+            .iter()
+            .position(|c| c.name == category.0)
+            .context("カテゴリーが見つかりません")?;
+        let info = categories.remove(index);
+        let position = position.min(categories.len());
+        categories.insert(position, info);
+        Ok(())
+    }
+
+    /// カテゴリーを削除します。タスクが残っている場合は`move_to`に移し、その数を返します。
+    pub fn remove_category(
+        &self,
+        actor: UserId,
+        category: &Category,
+        move_to: Option<&Category>,
+    ) -> Result<usize, Error> {
+        {
+            let categories = self.categories.lock().unwrap();
+            anyhow::ensure!(
+                categories.iter().any(|c| c.name == category.0),
+                "カテゴリーが見つかりません"
+            );
+            // カテゴリーがなくなるとタスクを追加できなくなる
+            anyhow::ensure!(categories.len() > 1, "最後のカテゴリーは削除できません");
+        }
+        let count = self
+            .tasks
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|task| &task.category == category)
+            .count();
+        let moved = match move_to {
+            Some(move_to) => {
+                anyhow::ensure!(
+                    move_to != category,
+                    "移動先に同じカテゴリーは指定できません"
+                );
+                self.replace_category(actor, category, move_to)
+            }
+            None => {
+                anyhow::ensure!(
+                    count == 0,
+                    "このカテゴリーのタスクが{}件あります。移動先を指定してください",
+                    count
+                );
+                0
+            }
+        };
+        self.categories
+            .lock()
+            .unwrap()
+            .retain(|c| c.name != category.0);
+        Ok(moved)
+    }
+
+    fn replace_category(&self, actor: UserId, from: &Category, to: &Category) -> usize {
+        if from == to {
+            return 0;
+        }
+        let count = self.rewrite_tasks(actor, |task| {
+            let matched = &task.category == from;
+            if matched {
+                task.category = to.clone();
+            }
+            matched
+        });
+        for feed in self.calendar_feeds.lock().unwrap().iter_mut() {
+            if feed.category.as_ref() == Some(from) {
+                feed.category = Some(to.clone());
+            }
+        }
+        count
+    }
+
+    fn replace_subject(&self, actor: UserId, from: &str, to: &Subject) -> usize {
+        let from = Subject::Set(from.to_string());
+        let count = self.rewrite_tasks(actor, |task| {
+            let matched = task.subject == from;
+            if matched {
+                task.subject = to.clone();
+            }
+            matched
+        });
+        for feed in self.calendar_feeds.lock().unwrap().iter_mut() {
+            if feed.subject.as_ref() == Some(&from) {
+                feed.subject = Some(to.clone());
+            }
+        }
+        count
+    }
+
+    // 名前の変更に合わせて、タスク・ゴミ箱のタスク・履歴の中のタスクを書き換え、書き換えたタスクの数を返す
+    // 履歴も書き換えるので、以前の変更を取り消したり以前の版に戻したりしても古い名前には戻らない
+    // 書き換えたタスクは取り消せない変更としてまとめて記録し、Webhookにも送る
+    fn rewrite_tasks(&self, actor: UserId, rewrite: impl Fn(&mut Task) -> bool) -> usize {
+        let events = {
+            let mut tasks = self.tasks.lock().unwrap();
+            let changed = tasks
+                .iter()
+                .filter_map(|task| {
+                    let mut after = task.clone();
+                    rewrite(&mut after).then(|| (task.clone(), after))
+                })
+                .collect::<Vec<_>>();
+            tasks.extend(changed.iter().map(|(_, after)| after.clone()));
+
+            for entry in self.trash.lock().unwrap().iter_mut() {
+                if let TrashItem::Task(task) = &mut entry.item {
+                    rewrite(task);
+                }
+            }
+            let mut history = self.history.lock().unwrap();
+            for event in history.iter_mut() {
+                for task in event.before.iter_mut().chain(event.after.iter_mut()) {
+                    rewrite(task);
+                }
+            }
+
+            let batch = Uuid::new_v4();
+            let timestamp = Local::now();
+            let events = changed
+                .into_iter()
+                .map(|(before, after)| TaskEvent {
+                    id: Uuid::new_v4(),
+                    task_id: after.id,
+                    actor,
+                    timestamp,
+                    before: Some(before),
+                    after: Some(after),
+                    reverts: None,
+                    batch: Some(batch),
+                    rename: true,
+                })
+                .collect::<Vec<_>>();
+            history.extend(events.iter().cloned());
+            events
+        };
+        let webhooks = self.webhooks.lock().unwrap().clone();
+        for event in &events {
+            webhook::dispatch(webhooks.clone(), event.into());
+        }
+        events.len()
+    }
+
+    /// 同じタスクが既にあるかどうか
+    pub fn has_duplicate(&self, task: &Task) -> bool {
+        self.tasks.lock().unwrap().iter().any(|t| {
+            t.id == task.id
+                || (t.subject == task.subject
+                    && t.details == task.details
+                    && t.datetime == task.datetime)
+        })
+    }
+
+    pub fn add_task(&self, actor: UserId, task: Task) -> TaskEvent {
+        self.apply(actor, task.id, Some(task))
+    }
+
+    /// 複数のタスクを同じ`batch`で追加し、`undo_batch`でまとめて取り消せるようにします。
+    pub fn add_tasks(&self, actor: UserId, tasks: Vec<Task>) -> Vec<TaskEvent> {
+        let batch = Some(Uuid::new_v4());
+        tasks
+            .into_iter()
+            .map(|task| {
+                self.try_apply(actor, task.id, Some(task), None, batch, |_| Ok(()))
+                    .expect("Applying without requirements never fails")
+            })
+            .collect()
+    }
+
+    pub fn edit_task(&self, actor: UserId, task: Task) -> Result<TaskEvent, Error> {
+        self.try_apply(actor, task.id, Some(task), None, None, |before| {
+            anyhow::ensure!(before.is_some(), "Task already removed");
+            Ok(())
+        })
+    }
+
+    /// 繰り返すタスクの1回だけを、繰り返しから除いて別のタスクにします。
+    /// 追加したタスクの変更、繰り返すタスクの変更の順に記録を返します。
+    /// 2つの変更は同じ`batch`で記録するので、`undo_batch`でまとめて取り消せます。
+    pub fn detach_occurrence(
+        &self,
+        actor: UserId,
+        series_id: Uuid,
+        date: NaiveDate,
+        task: Task,
+    ) -> Result<(TaskEvent, TaskEvent), Error> {
+        let mut series = self.task(series_id).context("Task already removed")?;
+        series
+            .recurrence
+            .as_mut()
+            .context("Task is not recurring")?
+            .exceptions
+            .insert(date);
+        let batch = Some(Uuid::new_v4());
+        let series_event =
+            self.try_apply(actor, series_id, Some(series), None, batch, |before| {
+                anyhow::ensure!(before.is_some(), "Task already removed");
+                Ok(())
+            })?;
+        let task = Task {
+            id: Uuid::new_v4(),
+            recurrence: None,
+            ..task
+        };
+        let event = self.try_apply(actor, task.id, Some(task), None, batch, |_| Ok(()))?;
+        Ok((event, series_event))
+    }
+
+    /// タスクを削除し、ゴミ箱に移動します。
+    pub fn remove_task(&self, actor: UserId, id: Uuid) -> Result<TaskEvent, Error> {
+        let event = self.try_apply(actor, id, None, None, None, |before| {
+            anyhow::ensure!(before.is_some(), "Task already removed");
+            Ok(())
+        })?;
+        self.move_to_trash(actor, TrashItem::Task(event.before.clone().unwrap()));
+        Ok(event)
+    }
+
+    /// 教科を削除し、ゴミ箱に移動します。
+    /// タスクが残っている場合は`move_to`に書き換え、その数を返します。
+    pub fn remove_subject(
+        &self,
+        actor: UserId,
+        subject: &str,
+        move_to: Option<&Subject>,
+    ) -> Result<usize, Error> {
+        let (info, moved) = {
+            let mut subjects = self.subjects.lock().unwrap();
+            let index = subjects
+                .iter()
+                .position(|s| s.name == subject)
+                .context("Subject already removed")?;
+            let moved = match move_to {
+                Some(move_to) => {
+                    anyhow::ensure!(
+                        move_to != &Subject::Set(subject.to_string()),
+                        "移動先に同じ教科は指定できません"
+                    );
+                    self.replace_subject(actor, subject, move_to)
+                }
+                None => {
+                    let count = self.subject_task_count(subject);
+                    anyhow::ensure!(
+                        count == 0,
+                        "この教科のタスクが{}件あります。移動先を指定してください",
+                        count
+                    );
+                    0
+                }
+            };
+            (subjects.remove(index), moved)
+        };
+        self.move_to_trash(actor, TrashItem::Subject(info));
+        Ok(moved)
+    }
+
+    fn move_to_trash(&self, actor: UserId, item: TrashItem) {
+        self.trash.lock().unwrap().push(TrashEntry {
+            id: Uuid::new_v4(),
+            item,
+            deleted_by: actor,
+            deleted_at: Local::now(),
+        });
+    }
+
+    /// ゴミ箱の項目を元に戻します。
+    pub fn restore_from_trash(&self, actor: UserId, entry_id: Uuid) -> Result<TrashEntry, Error> {
+        let entry = self
+            .trash
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|e| e.id == entry_id)
+            .cloned()
+            .context("Trash entry not found")?;
+        match &entry.item {
+            TrashItem::Task(task) => {
+                self.try_apply(actor, task.id, Some(task.clone()), None, None, |current| {
+                    anyhow::ensure!(current.is_none(), "同じタスクがすでに存在します");
+                    Ok(())
+                })?;
+            }
+            TrashItem::Subject(subject) => {
+                anyhow::ensure!(
+                    self.add_subject(subject.clone())?,
+                    "同じ教科がすでに存在します"
+                );
+                self.trash.lock().unwrap().retain(|e| e.id != entry_id);
+            }
+        }
+        Ok(entry)
+    }
+
+    pub fn trash_retention_days(&self) -> u32 {
+        self.trash_retention_days
+            .lock()
+            .unwrap()
+            .unwrap_or(DEFAULT_TRASH_RETENTION_DAYS)
+    }
+
+    /// 保持期間を過ぎたゴミ箱の項目を完全に削除し、削除した数を返します。
+    pub fn purge_trash(&self, now: DateTime<Local>) -> usize {
+        let retention = chrono::Duration::days(self.trash_retention_days() as i64);
+        self.purge_trash_where(|e| now - e.deleted_at >= retention)
+    }
+
+    /// ゴミ箱の項目をすべて完全に削除し、削除した数を返します。
+    pub fn empty_trash(&self) -> usize {
+        self.purge_trash_where(|_| true)
+    }
+
+    // 完全に削除したタスクは、変更の取り消しや以前の版に戻すことで復活しないよう履歴も消す
+    // 元に戻せなくなったタスクの完了の記録も消す
+    fn purge_trash_where(&self, purge: impl Fn(&TrashEntry) -> bool) -> usize {
+        let (purged, remaining) = {
+            let mut trash = self.trash.lock().unwrap();
+            let (purged, remaining) = std::mem::take(&mut *trash)
+                .into_iter()
+                .partition::<Vec<_>, _>(|e| purge(e));
+            trash.extend(remaining);
+            let remaining = trash
+                .iter()
+                .filter_map(|e| match &e.item {
+                    TrashItem::Task(task) => Some(task.id),
+                    TrashItem::Subject(_) => None,
+                })
+                .collect::<BTreeSet<_>>();
+            (purged, remaining)
+        };
+        let tasks = self.tasks.lock().unwrap();
+        let purged_ids = purged
+            .iter()
+            .filter_map(|e| match &e.item {
+                TrashItem::Task(task) => Some(task.id),
+                TrashItem::Subject(_) => None,
+            })
+            .filter(|id| tasks.get(*id).is_none() && !remaining.contains(id))
+            .collect::<BTreeSet<_>>();
+        self.history
+            .lock()
+            .unwrap()
+            .retain(|e| !purged_ids.contains(&e.task_id));
+        self.completions
+            .lock()
+            .unwrap()
+            .retain(|c| tasks.get(c.task_id).is_some() || remaining.contains(&c.task_id));
+        purged.len()
+    }
+
+    pub fn is_completed(&self, task: &Task, user_id: UserId) -> bool {
+        self.completions
+            .lock()
+            .unwrap()
+            .contains(&Completion::new(task, user_id))
+    }
+
+    /// タスクの完了と未完了を切り替え、切り替えた後に完了しているかを返します。
+    pub fn toggle_completion(&self, task: &Task, user_id: UserId) -> bool {
+        let completion = Completion::new(task, user_id);
+        let mut completions = self.completions.lock().unwrap();
+        if completions.remove(&completion) {
+            false
+        } else {
+            completions.insert(completion);
+            true
+        }
+    }
+
+    /// タスクを完了したユーザーの一覧を返します。
+    pub fn completed_users(&self, task: &Task) -> Vec<UserId> {
+        let occurrence = task.occurrence_date();
+        self.completions
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|c| c.task_id == task.id && c.occurrence == occurrence)
+            .map(|c| c.user_id)
+            .collect()
+    }
+
+    /// タスクを指定した状態にして、その変更を履歴に記録します。
+    /// `None`を指定するとタスクを削除します。
+    pub fn apply(&self, actor: UserId, task_id: Uuid, after: Option<Task>) -> TaskEvent {
+        self.try_apply(actor, task_id, after, None, None, |_| Ok(()))
+            .expect("Applying without requirements never fails")
+    }
+
+    /// 変更を取り消し、変更前の状態に戻します。
+    /// すでに取り消されている場合や、その後にタスクが変更されている場合は失敗します。
+    pub fn undo(&self, actor: UserId, event_id: Uuid) -> Result<TaskEvent, Error> {
+        let event = self
+            .history
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|e| e.id == event_id)
+            .cloned()
+            .context("Event not found")?;
+        anyhow::ensure!(!self.is_undone(event_id), "すでに取り消されています");
+        anyhow::ensure!(
+            !event.rename,
+            "名前の変更に合わせた書き換えは取り消せません"
+        );
+
+        self.try_apply(
+            actor,
+            event.task_id,
+            event.before,
+            Some(event_id),
+            None,
+            |current| {
+                anyhow::ensure!(
+                    current == event.after.as_ref(),
+                    "その後にタスクが変更されているため取り消せません"
+                );
+                Ok(())
+            },
+        )
+    }
+
+    /// 変更を取り消します。まとめて行った変更の場合は、同じ`batch`の変更をすべて新しい順に取り消します。
+    /// 取り消しの記録を新しい順に返します。
+    pub fn undo_batch(&self, actor: UserId, event_id: Uuid) -> Result<Vec<TaskEvent>, Error> {
+        let events = {
+            let history = self.history.lock().unwrap();
+            let event = history
+                .iter()
+                .find(|e| e.id == event_id)
+                .context("Event not found")?;
+            match event.batch {
+                Some(batch) => history
+                    .iter()
+                    .filter(|e| e.batch == Some(batch))
+                    .cloned()
+                    .collect::<Vec<_>>(),
+                None => vec![event.clone()],
+            }
+        };
+        // 一部だけ取り消されることがないよう、先にすべて取り消せるか確かめる
+        for event in &events {
+            anyhow::ensure!(
+                self.task(event.task_id).as_ref() == event.after.as_ref(),
+                "その後にタスクが変更されているため取り消せません"
+            );
+        }
+        events
+            .iter()
+            .rev()
+            .map(|event| self.undo(actor, event.id))
+            .collect()
+    }
+
+    pub fn is_undone(&self, event_id: Uuid) -> bool {
+        self.history
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|e| e.reverts == Some(event_id))
+    }
+
+    /// ユーザーが`window`以内に最後に行った、まだ取り消されていない変更を返します。
+    pub fn last_undoable(&self, actor: UserId, window: std::time::Duration) -> Option<TaskEvent> {
+        let since = Local::now() - chrono::Duration::from_std(window).ok()?;
+        let history = self.history.lock().unwrap().clone();
+        history
+            .into_iter()
+            .rev()
+            .take_while(|e| e.timestamp >= since)
+            .filter(|e| e.actor == actor && e.reverts.is_none() && !e.rename)
+            .find(|e| !self.is_undone(e.id))
+    }
+
+    fn try_apply(
+        &self,
+        actor: UserId,
+        task_id: Uuid,
+        after: Option<Task>,
+        reverts: Option<Uuid>,
+        batch: Option<Uuid>,
+        check: impl FnOnce(Option<&Task>) -> Result<(), Error>,
+    ) -> Result<TaskEvent, Error> {
+        let before = {
+            let mut tasks = self.tasks.lock().unwrap();
+            let before = tasks.get(task_id).cloned();
+            check(before.as_ref())?;
+            tasks.remove(task_id);
+            tasks.extend(after.clone());
+            before
+        };
+        if after.is_some() {
+            // 削除を取り消した場合などは、ゴミ箱に残っている同じタスクを取り除く
+            self.trash
+                .lock()
+                .unwrap()
+                .retain(|e| !matches!(&e.item, TrashItem::Task(t) if t.id == task_id));
+        }
+        let event = TaskEvent {
+            id: Uuid::new_v4(),
+            task_id,
+            actor,
+            timestamp: Local::now(),
+            before,
+            after,
+            reverts,
+            batch,
+            rename: false,
+        };
+        self.history.lock().unwrap().push(event.clone());
+        webhook::dispatch(self.webhooks.lock().unwrap().clone(), (&event).into());
+        Ok(event)
+    }
+
+    /// タスクの変更履歴を古い順に返します。
+    pub fn task_history(&self, task_id: Uuid) -> Vec<TaskEvent> {
+        self.history
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|e| e.task_id == task_id)
+            .cloned()
+            .collect()
+    }
+
+    /// タスクを最初に追加したユーザー。履歴が残っていなければNoneを返します。
+    pub fn creator(&self, task_id: Uuid) -> Option<UserId> {
+        self.history
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|e| e.task_id == task_id && e.before.is_none())
+            .map(|e| e.actor)
+    }
+
+    /// 復元したデータで中身を置き換えます。
+    /// 実行中のコマンドが持っている`Arc`の変更も失われないよう、同じオブジェクトを書き換えます。
+    /// パネルの待ち受けはそのままにするので、必要なら呼び出し側で作り直してください。
+    pub fn replace_with(&self, restored: GuildData) {
+        let GuildData {
+            tasks,
+            subjects,
+            suggest_times,
+            panel_message,
+            ping_channel,
+            ping_role,
+            log_channel,
+            history,
+            trash,
+            trash_retention_days,
+            calendar_feeds,
+            webhooks,
+            categories,
+            completions,
+            panel_listener: _,
+        } = restored;
+        // 置き換えている間にタスクが変更されないよう、他と同じ順で教科とタスクのロックを持っておく
+        let mut current_subjects = self.subjects.lock().unwrap();
+        let mut current_tasks = self.tasks.lock().unwrap();
+        *current_subjects = subjects.into_inner().unwrap();
+        *current_tasks = tasks.into_inner().unwrap();
+        *self.suggest_times.lock().unwrap() = suggest_times.into_inner().unwrap();
+        *self.panel_message.lock().unwrap() = panel_message.into_inner().unwrap();
+        *self.ping_channel.lock().unwrap() = ping_channel.into_inner().unwrap();
+        *self.ping_role.lock().unwrap() = ping_role.into_inner().unwrap();
+        *self.log_channel.lock().unwrap() = log_channel.into_inner().unwrap();
+        *self.history.lock().unwrap() = history.into_inner().unwrap();
+        *self.trash.lock().unwrap() = trash.into_inner().unwrap();
+        *self.trash_retention_days.lock().unwrap() = trash_retention_days.into_inner().unwrap();
+        *self.calendar_feeds.lock().unwrap() = calendar_feeds.into_inner().unwrap();
+        *self.webhooks.lock().unwrap() = webhooks.into_inner().unwrap();
+        *self.categories.lock().unwrap() = categories.into_inner().unwrap();
+        *self.completions.lock().unwrap() = completions.into_inner().unwrap();
+    }
+}
+
+/// 短い名前や別名が他の教科と重なると、どちらの教科か判別できなくなる
+fn ensure_distinct_names<'a>(
+    subjects: &[SubjectInfo],
+    subject: &str,
+    names: impl IntoIterator<Item = &'a String>,
+) -> Result<(), Error> {
+    for name in names {
+        if let Some(other) = subjects
+            .iter()
+            .find(|s| s.name != subject && s.matches(name))
+        {
+            anyhow::bail!("「{}」は{}の名前と重なっています", name, other.name);
+        }
+    }
     Ok(())
 }
 
-pub fn load() -> Result<Data, Error> {
-    let data = fs::read_to_string(FILE_PATH)?;
-    let data = serde_json::from_str(&data).expect("Failed to parse data.json");
-    Ok(data)
+#[derive(Debug, Default, Clone)]
+pub struct Data {
+    pub guilds: Arc<Mutex<BTreeMap<GuildId, Arc<GuildData>>>>,
+    dirty: Arc<Mutex<BTreeSet<GuildId>>>,
+    save_requested: Arc<Notify>,
+    saving: Arc<Mutex<()>>,
+}
+
+// 連続した変更をまとめて保存するまでの待ち時間
+const SAVE_DELAY: std::time::Duration = std::time::Duration::from_secs(2);
+
+impl Data {
+    pub fn guild(&self, guild_id: GuildId) -> Arc<GuildData> {
+        self.guilds
+            .lock()
+            .unwrap()
+            .entry(guild_id)
+            .or_default()
+            .clone()
+    }
+
+    /// ギルドのデータの保存を予約します。
+    /// 実際の書き込みは`run_saver`がまとめて行います。
+    pub fn request_save(&self, guild_id: GuildId) {
+        self.dirty.lock().unwrap().insert(guild_id);
+        self.save_requested.notify_one();
+    }
+
+    pub async fn run_saver(self) {
+        loop {
+            self.save_requested.notified().await;
+            tokio::time::sleep(SAVE_DELAY).await;
+            if let Err(e) = self.flush() {
+                println!("Failed to save data: {:?}", e);
+            }
+        }
+    }
+
+    /// 保存が予約されているギルドのデータをすぐに書き込みます。
+    pub fn flush(&self) -> Result<(), Error> {
+        let _saving = self.saving.lock().unwrap();
+        let dirty = std::mem::take(&mut *self.dirty.lock().unwrap());
+        let mut result = Ok(());
+        for guild_id in dirty {
+            if let Err(e) = save(guild_id, &self.guild(guild_id)) {
+                // 次回の保存で再試行する
+                self.dirty.lock().unwrap().insert(guild_id);
+                result = Err(e);
+            }
+        }
+        result
+    }
+}
+
+/// コマンドを実行したギルドのIDとデータを取得します。
+pub fn of(ctx: PoiseContext<'_>) -> Result<(GuildId, Arc<GuildData>), Error> {
+    let guild_id = ctx.guild_id().context("Not in a guild")?;
+    Ok((guild_id, ctx.data().guild(guild_id)))
+}
+
+// ギルドごとに分割する前の保存先
+pub const LEGACY_FILE_PATH: &str = "data.json";
+// 引き継ぎが終わった後の名前
+pub const MIGRATED_LEGACY_FILE_PATH: &str = "data.json.migrated";
+
+pub fn save(guild_id: GuildId, data: &GuildData) -> Result<(), Error> {
+    storage::backend().save(guild_id, data)
+}
+
+pub fn load_all() -> Result<BTreeMap<GuildId, GuildData>, Error> {
+    storage::backend().load_all()
+}
+
+pub fn load_legacy() -> Result<Option<GuildData>, Error> {
+    if !Path::new(LEGACY_FILE_PATH).exists() {
+        return Ok(None);
+    }
+    Ok(Some(storage::JsonStorage::load_file(LEGACY_FILE_PATH)?))
+}
+
+/// 引き継ぎが終わったファイルを、次の起動で読み込まないように名前を変えます。
+pub fn archive_legacy() -> Result<(), Error> {
+    std::fs::rename(LEGACY_FILE_PATH, MIGRATED_LEGACY_FILE_PATH)
+        .with_context(|| format!("Failed to rename {}", LEGACY_FILE_PATH))
 }
@@ -0,0 +1,32 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, TimeZone};
+use uuid::Uuid;
+
+use crate::{Category, Subject, Task};
+
+pub fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+pub fn time(hour: u32, minute: u32) -> NaiveTime {
+    NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+}
+
+pub fn datetime(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+    Local
+        .with_ymd_and_hms(year, month, day, hour, minute, 0)
+        .unwrap()
+}
+
+/// 2024/10/18 9:00の「宿題」「数学」のタスク。他の項目は構造体更新構文で変える
+pub fn task(details: &str) -> Task {
+    Task {
+        category: Category("宿題".into()),
+        subject: Subject::Set("数学".into()),
+        details: details.into(),
+        datetime: datetime(2024, 10, 18, 9, 0),
+        end: None,
+        id: Uuid::new_v4(),
+        all_day: false,
+        recurrence: None,
+    }
+}
@@ -0,0 +1,443 @@
+use anyhow::{Context as _, Error};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+use uuid::Uuid;
+
+use crate::{Subject, Task};
+
+/// 読み込んだVEVENTのうち、タスクに使う項目
+#[derive(Debug, Clone, Default)]
+pub struct IcsEvent {
+    pub uid: Option<String>,
+    pub summary: Option<String>,
+    pub description: Option<String>,
+    pub start: Option<IcsDateTime>,
+    // 日付の場合は、最終日の翌日を指す
+    pub end: Option<IcsDateTime>,
+    pub categories: Vec<String>,
+    // 読み取れなかった項目がある場合、最初のエラー。ファイル全体ではなくこの予定だけを失敗にする
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum IcsDateTime {
+    DateTime(DateTime<Local>),
+    Date(NaiveDate),
+}
+
+/// 書き出すカレンダーの設定
+pub struct IcsOptions {
+    pub name: String,
+    // 予定の何分前に通知するか
+    pub reminder: Option<Duration>,
+}
+
+/// タスクをRFC 5545のiCalendar形式で書き出します。
+pub fn render<'a>(tasks: impl IntoIterator<Item = &'a Task>, options: &IcsOptions) -> String {
+    let now = Local::now();
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".into(),
+        "PRODID:-//task-bot-rs//JA".into(),
+        "CALSCALE:GREGORIAN".into(),
+        "METHOD:PUBLISH".into(),
+        format!("X-WR-CALNAME:{}", escape(&options.name)),
+    ];
+
+    for task in tasks {
+        let (summary, _, _) = task.to_field();
+        let mut categories = vec![escape(&task.category.to_string())];
+        if let Subject::Set(subject) = &task.subject {
+            categories.push(escape(subject));
+        }
+
+        lines.extend([
+            "BEGIN:VEVENT".to_string(),
+            match task.recurrence {
+                // 繰り返すタスクは各回を別の予定として書き出す
+                Some(_) => format!(
+                    "UID:{}-{}@task-bot-rs",
+                    task.id,
+                    task.datetime.format("%Y%m%d")
+                ),
+                None => format!("UID:{}@task-bot-rs", task.id),
+            },
+            format!("DTSTAMP:{}", format_utc(now)),
+            if task.all_day {
+                format!("DTSTART;VALUE=DATE:{}", task.datetime.format("%Y%m%d"))
+            } else {
+                format!("DTSTART:{}", format_utc(task.datetime))
+            },
+            format!("SUMMARY:{}", escape(&summary)),
+            format!("DESCRIPTION:{}", escape(&task.details)),
+            format!("CATEGORIES:{}", categories.join(",")),
+        ]);
+        // 終日の予定のDTENDは、最終日の翌日を指定する
+        match (task.all_day, task.end) {
+            (true, Some(_)) => lines.push(format!(
+                "DTEND;VALUE=DATE:{}",
+                task.end_datetime().format("%Y%m%d")
+            )),
+            (false, Some(end)) => lines.push(format!("DTEND:{}", format_utc(end))),
+            (_, None) => {}
+        }
+        if let Some(reminder) = options.reminder {
+            lines.extend([
+                "BEGIN:VALARM".to_string(),
+                "ACTION:DISPLAY".into(),
+                format!("DESCRIPTION:{}", escape(&summary)),
+                format!("TRIGGER:-PT{}M", reminder.num_minutes()),
+                "END:VALARM".into(),
+            ]);
+        }
+        lines.push("END:VEVENT".into());
+    }
+    lines.push("END:VCALENDAR".into());
+
+    lines.iter().map(|line| fold(line)).collect()
+}
+
+/// `render`で書き出したUIDから、タスクのIDと、繰り返すタスクの場合はその回の日付を読み取ります。
+/// 他のアプリで作られたUIDの場合はNoneを返します。
+pub fn parse_uid(uid: &str) -> Option<(Uuid, Option<NaiveDate>)> {
+    let uid = uid.strip_suffix("@task-bot-rs")?;
+    if let Ok(id) = uid.parse() {
+        return Some((id, None));
+    }
+    let (id, date) = uid.rsplit_once('-')?;
+    Some((
+        id.parse().ok()?,
+        Some(NaiveDate::parse_from_str(date, "%Y%m%d").ok()?),
+    ))
+}
+
+fn format_utc(datetime: DateTime<Local>) -> String {
+    datetime
+        .with_timezone(&Utc)
+        .format("%Y%m%dT%H%M%SZ")
+        .to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+// 1行は75オクテットまでで、超える場合は空白から始まる行に折り返す
+fn fold(line: &str) -> String {
+    const MAX_OCTETS: usize = 75;
+
+    let mut folded = String::new();
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > MAX_OCTETS {
+            folded.push_str("\r\n ");
+            octets = 1;
+        }
+        folded.push(c);
+        octets += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+/// iCalendar形式のテキストからVEVENTを読み込みます。
+pub fn parse(text: &str) -> Result<Vec<IcsEvent>, Error> {
+    // 空白から始まる行は前の行の続き
+    let mut lines: Vec<String> = vec![];
+    for line in text.lines() {
+        match line.strip_prefix([' ', '\t']) {
+            Some(rest) => lines
+                .last_mut()
+                .context("Unexpected continuation line")?
+                .push_str(rest),
+            None => lines.push(line.to_string()),
+        }
+    }
+
+    let mut events = vec![];
+    let mut event: Option<IcsEvent> = None;
+    let mut depth = 0;
+    for line in lines {
+        let (name, value) = line.split_once(':').unwrap_or((&line, ""));
+        let (name, params) = name.split_once(';').unwrap_or((name, ""));
+        match (name.to_ascii_uppercase().as_str(), value) {
+            ("BEGIN", "VEVENT") => event = Some(IcsEvent::default()),
+            ("END", "VEVENT") => events.extend(event.take()),
+            // VALARMなど、VEVENTの中の要素の項目は読み飛ばす
+            ("BEGIN", _) if event.is_some() => depth += 1,
+            ("END", _) if event.is_some() => depth -= 1,
+            (_, _) if depth > 0 => {}
+            (name, value) => {
+                let Some(event) = event.as_mut() else {
+                    continue;
+                };
+                match name {
+                    "UID" => event.uid = Some(value.to_string()),
+                    "SUMMARY" => event.summary = Some(unescape(value)),
+                    "DESCRIPTION" => event.description = Some(unescape(value)),
+                    "CATEGORIES" => event
+                        .categories
+                        .extend(split_escaped(value).iter().map(|c| unescape(c))),
+                    "DTSTART" | "DTEND" => match parse_datetime(params, value) {
+                        Ok(datetime) if name == "DTSTART" => event.start = Some(datetime),
+                        Ok(datetime) => event.end = Some(datetime),
+                        Err(e) => {
+                            event
+                                .error
+                                .get_or_insert(format!("{}を読み取れませんでした: {}", name, e));
+                        }
+                    },
+                    _ => {}
+                }
+            }
+        }
+    }
+    Ok(events)
+}
+
+fn parse_datetime(params: &str, value: &str) -> Result<IcsDateTime, Error> {
+    if params.to_ascii_uppercase().contains("VALUE=DATE") && !value.contains('T') {
+        return Ok(IcsDateTime::Date(
+            NaiveDate::parse_from_str(value, "%Y%m%d").context("Invalid date")?,
+        ));
+    }
+    let datetime = match value.strip_suffix('Z') {
+        Some(utc) => Utc
+            .from_utc_datetime(
+                &NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").context("Invalid date")?,
+            )
+            .with_timezone(&Local),
+        // TZIDを指定された時刻や浮動時刻は、ローカル時刻として扱う
+        None => Local
+            .from_local_datetime(
+                &NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").context("Invalid date")?,
+            )
+            .single()
+            .context("Invalid date")?,
+    };
+    Ok(IcsDateTime::DateTime(datetime))
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n' | 'N') => unescaped.push('\n'),
+                Some(c) => unescaped.push(c),
+                None => {}
+            }
+        } else {
+            unescaped.push(c);
+        }
+    }
+    unescaped
+}
+
+// エスケープされていないカンマで区切る
+fn split_escaped(text: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            ',' if !escaped => {
+                parts.push(&text[start..i]);
+                start = i + 1;
+            }
+            _ => escaped = false,
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{date, datetime, task},
+        recurrence::{Recurrence, RecurrenceEnd, RecurrenceRule},
+    };
+
+    #[test]
+    fn escapes_text() {
+        assert_eq!(escape("a,b;c\\d\ne"), r"a\,b\;c\\d\ne");
+    }
+
+    #[test]
+    fn folds_long_lines() {
+        assert_eq!(fold("SUMMARY:short"), "SUMMARY:short\r\n");
+
+        let line = format!("DESCRIPTION:{}", "あ".repeat(40));
+        let folded = fold(&line);
+        for part in folded.split("\r\n") {
+            assert!(part.len() <= 75, "{:?}", part);
+        }
+        // マルチバイト文字の途中では折り返さない
+        let unfolded = folded.trim_end_matches("\r\n").replace("\r\n ", "");
+        assert_eq!(unfolded, line);
+    }
+
+    #[test]
+    fn parses_rendered_uids() {
+        let id = Uuid::new_v4();
+        assert_eq!(parse_uid(&format!("{}@task-bot-rs", id)), Some((id, None)));
+        assert_eq!(
+            parse_uid(&format!("{}-20241018@task-bot-rs", id)),
+            Some((id, Some(date(2024, 10, 18))))
+        );
+        assert_eq!(parse_uid(&format!("{}@example.com", id)), None);
+        assert_eq!(parse_uid("abc-20241018@task-bot-rs"), None);
+        assert_eq!(parse_uid(&format!("{}-2024@task-bot-rs", id)), None);
+
+        let recurring = Task {
+            recurrence: Some(Recurrence {
+                rule: RecurrenceRule::Daily(1),
+                end: RecurrenceEnd::Count(1),
+                exceptions: Default::default(),
+            }),
+            ..task("ワーク")
+        };
+        let options = IcsOptions {
+            name: "テスト".into(),
+            reminder: None,
+        };
+        let events = parse(&render([&recurring], &options)).unwrap();
+        assert_eq!(
+            parse_uid(events[0].uid.as_deref().unwrap()),
+            Some((recurring.id, Some(date(2024, 10, 18))))
+        );
+    }
+
+    #[test]
+    fn renders_all_day_task() {
+        let options = IcsOptions {
+            name: "テスト".into(),
+            reminder: Some(Duration::minutes(30)),
+        };
+        let task = Task {
+            datetime: datetime(2024, 10, 18, 0, 0),
+            end: Some(datetime(2024, 10, 20, 0, 0)),
+            all_day: true,
+            ..task("ワーク, p.10;11")
+        };
+        let ics = render(&[task], &options);
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert!(ics.contains("DTSTART;VALUE=DATE:20241018\r\n"));
+        // 終日の予定のDTENDは最終日の翌日
+        assert!(ics.contains("DTEND;VALUE=DATE:20241021\r\n"));
+        assert!(ics.contains("DESCRIPTION:ワーク\\, p.10\\;11\r\n"));
+        assert!(ics.contains("CATEGORIES:宿題,数学\r\n"));
+        assert!(ics.contains("TRIGGER:-PT30M\r\n"));
+    }
+
+    const CALENDAR: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VEVENT\r
+UID:first@example.com\r
+SUMMARY:数学\\, 小テスト\r
+DESCRIPTION:範囲は\\n教科書 p.10\r
+  から\r
+DTSTART:20241018T003000Z\r
+DTEND:20241018T013000Z\r
+CATEGORIES:テスト,数学\r
+BEGIN:VALARM\r
+DESCRIPTION:通知\r
+DTSTART:20240101T000000Z\r
+END:VALARM\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:second@example.com\r
+SUMMARY:文化祭\r
+DTSTART;VALUE=DATE:20241102\r
+DTEND;VALUE=DATE:20241104\r
+CATEGORIES:イベント\\,行事\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:broken@example.com\r
+SUMMARY:壊れた予定\r
+DTSTART:2024-10-18\r
+DTEND:2024-10-19\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    #[test]
+    fn parses_events() {
+        let events = parse(CALENDAR).unwrap();
+        assert_eq!(events.len(), 3);
+
+        let first = &events[0];
+        assert_eq!(first.uid.as_deref(), Some("first@example.com"));
+        assert_eq!(first.summary.as_deref(), Some("数学, 小テスト"));
+        // 折り返された行はつなげる
+        assert_eq!(
+            first.description.as_deref(),
+            Some("範囲は\n教科書 p.10 から")
+        );
+        assert_eq!(first.categories, ["テスト", "数学"]);
+        let Some(IcsDateTime::DateTime(start)) = first.start else {
+            panic!("{:?}", first.start);
+        };
+        assert_eq!(
+            start.with_timezone(&Utc),
+            Utc.with_ymd_and_hms(2024, 10, 18, 0, 30, 0).unwrap()
+        );
+        assert!(first.error.is_none());
+
+        let second = &events[1];
+        assert!(matches!(
+            second.start,
+            Some(IcsDateTime::Date(d)) if d == date(2024, 11, 2)
+        ));
+        assert!(matches!(
+            second.end,
+            Some(IcsDateTime::Date(d)) if d == date(2024, 11, 4)
+        ));
+        assert_eq!(second.categories, ["イベント,行事"]);
+    }
+
+    #[test]
+    fn reports_errors_per_event() {
+        let events = parse(CALENDAR).unwrap();
+        let broken = &events[2];
+        assert!(broken.start.is_none());
+        // 最初のエラーのみ残す
+        assert!(broken
+            .error
+            .as_deref()
+            .is_some_and(|e| e.starts_with("DTSTARTを読み取れませんでした")));
+        assert!(events[0].error.is_none() && events[1].error.is_none());
+    }
+
+    #[test]
+    fn parses_floating_time_as_local() {
+        let events =
+            parse("BEGIN:VEVENT\nDTSTART;TZID=Asia/Tokyo:20241018T090000\nEND:VEVENT\n").unwrap();
+        let Some(IcsDateTime::DateTime(start)) = events[0].start else {
+            panic!("{:?}", events[0].start);
+        };
+        assert_eq!(
+            start.naive_local(),
+            date(2024, 10, 18).and_hms_opt(9, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_leading_continuation_line() {
+        assert!(parse(" BEGIN:VEVENT\n").is_err());
+    }
+
+    #[test]
+    fn unescapes_and_splits() {
+        assert_eq!(unescape(r"a\,b\;c\\d\ne\N"), "a,b;c\\d\ne\n");
+        assert_eq!(split_escaped(r"a,b\,c,\\,d"), [r"a", r"b\,c", r"\\", "d"]);
+    }
+}
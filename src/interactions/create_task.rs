@@ -6,6 +6,7 @@ use futures::StreamExt;
 use poise::serenity_prelude::*;
 
 use crate::{
+    data,
     interactions::{select_date, select_time},
     utilities::format_date,
     Category, PartialTask, PoiseContext, Subject, Task,
@@ -17,34 +18,56 @@ pub async fn create_task(
     embed: Option<CreateEmbed>,
     defaults: PartialTask,
 ) -> Result<(ModalInteraction, Task), Error> {
+    let (_, guild_data) = data::of(ctx)?;
     const CATEGORY: &str = "category";
     const SUBJECT: &str = "subject";
     const DATE: &str = "date";
     const TIME: &str = "time";
+    // 時刻の選択肢のうち、終日を表す値
+    const ALL_DAY: &str = "all_day";
+    const END: &str = "end";
     const SUBMIT: &str = "submit";
+    // 「教科を指定しない」と合わせて25件に収める
+    const MAX_SUBJECT_OPTIONS: usize = 24;
 
-    let subjects = ctx.data().subjects.lock().unwrap().clone();
-    let suggest_times = ctx.data().suggest_times.lock().unwrap().clone();
+    let categories = guild_data.categories.lock().unwrap().clone();
+    let subjects = guild_data.subjects.lock().unwrap().clone();
+    let suggest_times = guild_data.suggest_times.lock().unwrap().clone();
 
     let components = |task: &PartialTask| {
         let category_options = CreateSelectMenuKind::String {
-            options: Category::VALUES
+            options: categories
                 .iter()
-                .map(|&c| {
-                    CreateSelectMenuOption::new(c, serde_json::to_string(&c).unwrap())
-                        .default_selection(task.category == Some(c))
+                .take(25)
+                .map(|c| {
+                    let option = CreateSelectMenuOption::new(&c.name, &c.name)
+                        .default_selection(task.category == Some(c.category()));
+                    match c.reaction() {
+                        Some(emoji) => option.emoji(emoji),
+                        None => option,
+                    }
                 })
                 .collect(),
         };
         let subject_options = CreateSelectMenuKind::String {
             options: subjects
                 .iter()
+                .take(MAX_SUBJECT_OPTIONS)
                 .map(|s| {
-                    CreateSelectMenuOption::new(
-                        s,
-                        serde_json::to_string(&Subject::Set(s.to_string())).unwrap(),
+                    let subject = Subject::Set(s.name.clone());
+                    let option = CreateSelectMenuOption::new(
+                        &s.name,
+                        serde_json::to_string(&subject).unwrap(),
                     )
-                    .default_selection(task.subject == Some(Subject::Set(s.to_string())))
+                    .default_selection(task.subject == Some(subject));
+                    let option = match &s.teacher {
+                        Some(teacher) => option.description(format!("担当: {}", teacher)),
+                        None => option,
+                    };
+                    match s.reaction() {
+                        Some(emoji) => option.emoji(emoji),
+                        None => option,
+                    }
                 })
                 .chain(iter::once(
                     CreateSelectMenuOption::new(
@@ -77,20 +100,22 @@ pub async fn create_task(
         let time_options = CreateSelectMenuKind::String {
             options: suggest_times
                 .iter()
+                .take(data::MAX_SUGGEST_TIMES)
                 .map(|(t, l)| {
                     CreateSelectMenuOption::new(
                         format!("{} ({})", l, t.format("%H:%M")),
                         serde_json::to_string(&Some(t)).unwrap(),
                     )
-                    .default_selection(task.time == Some(*t))
+                    .default_selection(!task.all_day && task.time == Some(*t))
                 })
-                .chain(iter::once(
+                .chain([
+                    CreateSelectMenuOption::new("終日", ALL_DAY).default_selection(task.all_day),
                     CreateSelectMenuOption::new(
                         "その他の時刻",
                         serde_json::to_string(&None::<NaiveTime>).unwrap(),
                     )
-                    .default_selection(task.time.is_none()),
-                ))
+                    .default_selection(!task.all_day && task.time.is_none()),
+                ])
                 .collect::<Vec<_>>(),
         };
 
@@ -99,22 +124,44 @@ pub async fn create_task(
                 CreateSelectMenu::new(CATEGORY, category_options).placeholder("カテゴリー"),
             ),
             CreateActionRow::SelectMenu(
-                CreateSelectMenu::new(SUBJECT, subject_options).placeholder("教科"),
+                CreateSelectMenu::new(SUBJECT, subject_options).placeholder(
+                    if subjects.len() > MAX_SUBJECT_OPTIONS {
+                        format!("教科 (多いため最初の{}件のみ表示)", MAX_SUBJECT_OPTIONS)
+                    } else {
+                        "教科".into()
+                    },
+                ),
             ),
             CreateActionRow::SelectMenu(
                 CreateSelectMenu::new(DATE, date_options)
                     .placeholder(task.date.map_or("日付".into(), format_date)),
             ),
-            CreateActionRow::SelectMenu(
-                CreateSelectMenu::new(TIME, time_options).placeholder(
-                    task.time
-                        .map_or("時間".into(), |x| x.format("%H:%M").to_string()),
+            CreateActionRow::SelectMenu(CreateSelectMenu::new(TIME, time_options).placeholder(
+                match task.time {
+                    _ if task.all_day => "終日".into(),
+                    Some(time) => time.format("%H:%M").to_string(),
+                    None => "時間".into(),
+                },
+            )),
+            CreateActionRow::Buttons(vec![
+                CreateButton::new(END).style(ButtonStyle::Secondary).label(
+                    match (task.has_end, task.end_date) {
+                        (false, _) => "終わりの日時: なし".into(),
+                        (true, None) => "終わりの日時: 送信後に選択".into(),
+                        (true, Some(date)) => format!(
+                            "終わりの日時: {}{}",
+                            format_date(date),
+                            task.end_time
+                                .filter(|_| !task.all_day)
+                                .map_or("".into(), |t| t.format(" %H:%M").to_string())
+                        ),
+                    },
                 ),
-            ),
-            CreateActionRow::Buttons(vec![CreateButton::new(SUBMIT)
-                .style(ButtonStyle::Primary)
-                .label("送信")
-                .disabled(task.category.is_none() || task.subject.is_none())]),
+                CreateButton::new(SUBMIT)
+                    .style(ButtonStyle::Primary)
+                    .label("送信")
+                    .disabled(task.category.is_none() || task.subject.is_none()),
+            ]),
         ]
     };
 
@@ -157,7 +204,7 @@ pub async fn create_task(
             ComponentInteractionDataKind::StringSelect { values } => {
                 match interaction.data.custom_id.as_str() {
                     CATEGORY => {
-                        task.category.replace(serde_json::from_str(&values[0])?);
+                        task.category.replace(Category(values[0].clone()));
                     }
                     SUBJECT => {
                         task.subject.replace(serde_json::from_str(&values[0])?);
@@ -165,7 +212,12 @@ pub async fn create_task(
                     DATE => {
                         task.date = serde_json::from_str(&values[0])?;
                     }
+                    TIME if values[0] == ALL_DAY => {
+                        task.all_day = true;
+                        task.time = None;
+                    }
                     TIME => {
+                        task.all_day = false;
                         task.time = serde_json::from_str(&values[0])?;
                     }
                     _ => {}
@@ -175,11 +227,19 @@ pub async fn create_task(
                 );
                 interaction.create_response(&ctx, response).await?;
             }
-            ComponentInteractionDataKind::Button => {
-                if interaction.data.custom_id == SUBMIT {
-                    last_interaction.replace(interaction);
-                    break;
-                }
+            // 押すたびに、終わりの日時の有無を切り替える。設定済みの日時は選び直す
+            ComponentInteractionDataKind::Button if interaction.data.custom_id == END => {
+                task.has_end = !task.has_end;
+                task.end_date = None;
+                task.end_time = None;
+                let response = CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::default().components(components(&task)),
+                );
+                interaction.create_response(&ctx, response).await?;
+            }
+            ComponentInteractionDataKind::Button if interaction.data.custom_id == SUBMIT => {
+                last_interaction.replace(interaction);
+                break;
             }
             _ => {}
         }
@@ -199,7 +259,9 @@ pub async fn create_task(
         }
     };
 
+    // 終日のタスクは時刻を選ばない
     task.time = match task.clone().time {
+        _ if task.all_day => None,
         Some(time) => Some(time),
         None => {
             let (interaction, time) = select_time(
@@ -213,6 +275,37 @@ pub async fn create_task(
         }
     };
 
+    if task.has_end {
+        if task.end_date.is_none() {
+            let (interaction, date) = select_date(
+                ctx,
+                Some(last_interaction.clone().context("No interaction")?),
+                Some(
+                    CreateEmbed::default()
+                        .title("終わりの日付を選択")
+                        .color(Color::DARK_BLUE),
+                ),
+            )
+            .await?;
+            last_interaction.replace(interaction);
+            task.end_date = Some(date);
+        }
+        if !task.all_day && task.end_time.is_none() {
+            let (interaction, time) = select_time(
+                ctx,
+                Some(last_interaction.clone().context("No interaction")?),
+                Some(
+                    CreateEmbed::default()
+                        .title("終わりの時刻を選択")
+                        .color(Color::DARK_BLUE),
+                ),
+            )
+            .await?;
+            last_interaction.replace(interaction);
+            task.end_time = Some(time);
+        }
+    }
+
     let modal = CreateQuickModal::new("詳細入力")
         .field(
             CreateInputText::new(InputTextStyle::Short, "詳細", "")
@@ -1,5 +1,7 @@
 mod create_task;
 pub use create_task::create_task;
+mod select_occurrence;
+pub use select_occurrence::select_occurrence;
 mod select_task;
 pub use select_task::select_task;
 mod select_date;
@@ -179,11 +179,9 @@ pub async fn select_date(
                     _ => {}
                 }
             }
-            ComponentInteractionDataKind::Button => {
-                if interaction.data.custom_id == SUBMIT {
-                    last_interaction.replace(interaction);
-                    break;
-                }
+            ComponentInteractionDataKind::Button if interaction.data.custom_id == SUBMIT => {
+                last_interaction.replace(interaction);
+                break;
             }
             _ => {}
         }
@@ -0,0 +1,75 @@
+use anyhow::{Context as _, Error};
+use chrono::{Duration, Local, NaiveDate};
+use poise::serenity_prelude::*;
+
+use crate::{PoiseContext, Task};
+
+/// 繰り返すタスクのうち、どの回を対象にするかを選択させます。
+/// 「すべての回」が選ばれた場合はNoneを返します。
+pub async fn select_occurrence(
+    ctx: PoiseContext<'_>,
+    interaction: ComponentInteraction,
+    task: &Task,
+    embed: CreateEmbed,
+) -> Result<(ComponentInteraction, Option<NaiveDate>), Error> {
+    const OCCURRENCE: &str = "occurrence";
+    const ALL: &str = "all";
+
+    let today = Local::now().date_naive();
+    let mut occurrences = task
+        .occurrences()
+        .filter(|t| t.datetime.date_naive() >= today)
+        .take(25)
+        .collect::<Vec<_>>();
+    // 今後の回がない場合は、最後の回から選べるようにする
+    if occurrences.is_empty() {
+        occurrences = task.occurrences().collect();
+        occurrences = occurrences.split_off(occurrences.len().saturating_sub(25));
+    }
+
+    let mut components = vec![];
+    if !occurrences.is_empty() {
+        components.push(CreateActionRow::SelectMenu(
+            CreateSelectMenu::new(
+                OCCURRENCE,
+                CreateSelectMenuKind::String {
+                    options: occurrences
+                        .iter()
+                        .map(|t| {
+                            CreateSelectMenuOption::new(
+                                t.format_datetime(),
+                                t.datetime.date_naive().to_string(),
+                            )
+                        })
+                        .collect(),
+                },
+            )
+            .placeholder("この回のみ"),
+        ));
+    }
+    components.push(CreateActionRow::Buttons(vec![CreateButton::new(ALL)
+        .style(ButtonStyle::Primary)
+        .label("すべての回")]));
+
+    let response = CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::default()
+            .embed(embed)
+            .components(components),
+    );
+    interaction.create_response(ctx, response).await?;
+
+    let interaction = interaction
+        .get_response(ctx)
+        .await?
+        .await_component_interaction(ctx)
+        .author_id(ctx.author().id)
+        .timeout(Duration::seconds(60 * 30).to_std()?)
+        .await
+        .context("No interaction")?;
+
+    let date = match &interaction.data.kind {
+        ComponentInteractionDataKind::StringSelect { values } => Some(values[0].parse()?),
+        _ => None,
+    };
+    Ok((interaction, date))
+}
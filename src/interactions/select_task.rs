@@ -1,16 +1,16 @@
 use anyhow::{Context as _, Error};
 use chrono::Duration;
 use futures::StreamExt;
-use itertools::Itertools;
 use poise::serenity_prelude::*;
 
-use crate::{utilities::format_datetime, PoiseContext, Task};
+use crate::{data, utilities::truncate, PoiseContext, Task};
 
 pub async fn select_task(
     ctx: PoiseContext<'_>,
     interaction: Option<ComponentInteraction>,
     embed: Option<CreateEmbed>,
 ) -> Result<(ComponentInteraction, Task), Error> {
+    let (_, guild_data) = data::of(ctx)?;
     const TASK: &str = "task";
     const SUBMIT: &str = "submit";
     const PREV: &str = "prev";
@@ -18,18 +18,15 @@ pub async fn select_task(
 
     let mut page = 0;
     let components = |page: usize, selected_task: &Option<Task>| {
-        let options = ctx
-            .data()
+        let options = guild_data
             .tasks
             .lock()
             .unwrap()
             .iter()
-            .enumerate()
-            .sorted_by_key(|(_, task)| task.datetime)
             .rev()
-            .map(|(idx, task)| {
-                CreateSelectMenuOption::new(task.to_field().0, idx.to_string())
-                    .description(format_datetime(task.datetime))
+            .map(|task| {
+                CreateSelectMenuOption::new(truncate(&task.to_field().0, 100), task.id.to_string())
+                    .description(task.format_datetime())
                     .default_selection(selected_task.as_ref() == Some(task))
             })
             .skip(25 * page)
@@ -99,11 +96,9 @@ pub async fn select_task(
         match &interaction.data.kind {
             ComponentInteractionDataKind::StringSelect { values } => {
                 if interaction.data.custom_id == TASK {
-                    let tasks = ctx.data().tasks.lock().unwrap().clone();
                     task.replace(
-                        tasks
-                            .into_iter()
-                            .nth(values[0].parse::<usize>().unwrap())
+                        guild_data
+                            .task(values[0].parse()?)
                             .context("Invalid task")?,
                     );
                 }
@@ -55,7 +55,12 @@ pub async fn select_time(
 
     let message = if let Some(interaction) = interaction {
         let response = CreateInteractionResponse::UpdateMessage(
-            CreateInteractionResponseMessage::default().components(components(hour, minute)),
+            if let Some(embed) = embed {
+                CreateInteractionResponseMessage::default().embed(embed)
+            } else {
+                CreateInteractionResponseMessage::default()
+            }
+            .components(components(hour, minute)),
         );
         interaction.clone().create_response(ctx, response).await?;
         interaction.get_response(ctx).await?
@@ -102,11 +107,9 @@ pub async fn select_time(
                     _ => {}
                 }
             }
-            ComponentInteractionDataKind::Button => {
-                if interaction.data.custom_id == SUBMIT {
-                    last_interaction.replace(interaction);
-                    break;
-                }
+            ComponentInteractionDataKind::Button if interaction.data.custom_id == SUBMIT => {
+                last_interaction.replace(interaction);
+                break;
             }
             _ => {}
         }
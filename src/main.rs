@@ -1,13 +1,25 @@
-use anyhow::Error;
+use std::sync::Arc;
+
+use anyhow::{Context as _, Error};
 use data::{Category, Data, PartialTask, Subject, Task};
 use dotenvy::dotenv;
 use poise::serenity_prelude::*;
 
+mod api;
 mod commands;
 mod data;
+#[cfg(test)]
+mod fixtures;
+mod ics;
 mod interactions;
+mod natural_datetime;
 mod periodic;
+mod recurrence;
+mod spreadsheet;
+mod storage;
+mod task_store;
 mod utilities;
+mod webhook;
 
 pub type PoiseContext<'a> = poise::Context<'a, Data, Error>;
 
@@ -19,40 +31,120 @@ async fn event_handler(
 ) -> Result<(), Error> {
     if let FullEvent::Ready { data_about_bot } = event {
         println!("Logged in as {}", data_about_bot.user.name);
-        match data::load() {
-            Ok(restore) => {
-                *data.tasks.lock().unwrap() = restore.tasks.lock().unwrap().clone();
-                *data.subjects.lock().unwrap() = restore.subjects.lock().unwrap().clone();
-                *data.suggest_times.lock().unwrap() = restore.suggest_times.lock().unwrap().clone();
-                *data.panel_message.lock().unwrap() = *restore.panel_message.lock().unwrap();
-                *data.ping_channel.lock().unwrap() = *restore.ping_channel.lock().unwrap();
-                *data.ping_role.lock().unwrap() = *restore.ping_role.lock().unwrap();
-                *data.log_channel.lock().unwrap() = *restore.log_channel.lock().unwrap();
-                println!("Config restored:");
-                println!("{:#?}", data);
-            }
-            Err(_) => {
-                println!("Note: {} not found, using default data", data::FILE_PATH);
-                data::save(data)?;
+        tokio::spawn(periodic::wait(ctx.clone(), data.clone()));
+        for guild_data in data.guilds.lock().unwrap().values() {
+            if let Some(panel_message) = &*guild_data.panel_message.lock().unwrap() {
+                guild_data
+                    .panel_listener
+                    .lock()
+                    .unwrap()
+                    .replace(tokio::spawn(commands::panel::listen_panel_interactions(
+                        ctx.clone(),
+                        data.clone(),
+                        *panel_message,
+                    )));
             }
         }
-        tokio::spawn(periodic::wait(ctx.clone()));
-        if let Some(panel_message) = &*data.panel_message.lock().unwrap() {
-            data.panel_listener.lock().unwrap().replace(tokio::spawn(
-                commands::panel::listen_panel_interactions(ctx.clone(), *panel_message),
-            ));
+    }
+    // 通知メッセージのボタンは再起動後も押せるように、コレクターではなくここで受け取る
+    if let FullEvent::InteractionCreate {
+        interaction: Interaction::Component(interaction),
+    } = event
+    {
+        if interaction
+            .data
+            .custom_id
+            .starts_with(commands::completion::COMPLETE_PREFIX)
+        {
+            commands::completion::handle_complete_button(ctx, data, interaction).await?;
         }
     }
     Ok(())
 }
 
+/// ギルドごとに分割する前のデータを引き継ぎます。
+/// 引き継ぐ先は`LEGACY_GUILD_ID`のギルドか、未設定の場合はBotが参加している唯一のギルドです。
+async fn migrate_legacy(http: &Http, data: &Data) -> Result<(), Error> {
+    let Some(legacy) = data::load_legacy()? else {
+        return Ok(());
+    };
+    let guild_id = match std::env::var("LEGACY_GUILD_ID") {
+        Ok(id) => id.parse::<GuildId>().context("Invalid LEGACY_GUILD_ID")?,
+        Err(_) => match http.get_guilds(None, None).await?.as_slice() {
+            [guild] => guild.id,
+            _ => {
+                println!(
+                    "Note: {} was not migrated because the bot is not in exactly one guild; \
+                     set LEGACY_GUILD_ID to choose the guild",
+                    data::LEGACY_FILE_PATH
+                );
+                return Ok(());
+            }
+        },
+    };
+    if data.guilds.lock().unwrap().contains_key(&guild_id) {
+        println!(
+            "Note: {} was not migrated because guild {} already has data",
+            data::LEGACY_FILE_PATH,
+            guild_id
+        );
+        return Ok(());
+    }
+
+    data::save(guild_id, &legacy)?;
+    data.guilds
+        .lock()
+        .unwrap()
+        .insert(guild_id, Arc::new(legacy));
+    data::archive_legacy()?;
+    println!(
+        "Migrated {} to guild {} and renamed it to {}",
+        data::LEGACY_FILE_PATH,
+        guild_id,
+        data::MIGRATED_LEGACY_FILE_PATH
+    );
+    Ok(())
+}
+
 #[tokio::main]
-async fn main() {
+async fn main() -> Result<(), Error> {
     use commands::*;
 
     dotenv().expect(".env file not found");
 
     let token = std::env::var("DISCORD_TOKEN").expect("Missing DISCORD_TOKEN");
+    storage::init()?;
+
+    // 読み込みや変換に失敗した場合は、起動せずにエラーを返す
+    let data = Data::default();
+    let restore = data::load_all()?;
+    if restore.is_empty() {
+        println!("Note: no saved data found, using default data");
+    } else {
+        for (guild_id, guild_data) in restore {
+            // 変換後の形式で書き戻しておく
+            data::save(guild_id, &guild_data)?;
+            data.guilds
+                .lock()
+                .unwrap()
+                .insert(guild_id, Arc::new(guild_data));
+        }
+        println!("Config restored:");
+        println!("{:#?}", data);
+    }
+    migrate_legacy(&Http::new(&token), &data).await?;
+    let flush_data = data.clone();
+    let saver = tokio::spawn(data.clone().run_saver());
+    // HTTP_API_ADDRが設定されている場合のみ、HTTP APIを起動する
+    if let Ok(addr) = std::env::var("HTTP_API_ADDR") {
+        let addr = addr.parse().context("Invalid HTTP_API_ADDR")?;
+        let api_data = data.clone();
+        tokio::spawn(async move {
+            if let Err(e) = api::serve(api_data, addr).await {
+                eprintln!("HTTP API stopped: {:?}", e);
+            }
+        });
+    }
     let intents = GatewayIntents::non_privileged();
 
     let framework = poise::Framework::builder()
@@ -61,14 +153,40 @@ async fn main() {
                 modify_tasks::add_task(),
                 modify_tasks::remove_task(),
                 modify_tasks::edit_task(),
+                quick_add::quick_add(),
+                commands::recurrence::set_recurrence(),
+                modify_categories::add_category(),
+                modify_categories::edit_category(),
+                modify_categories::move_category(),
+                modify_categories::remove_category(),
                 modify_subjects::add_subjects(),
+                modify_subjects::subjects(),
+                modify_subjects::edit_subject(),
                 modify_subjects::remove_subject(),
+                modify_subjects::rename_subject(),
+                modify_subjects::merge_subjects(),
                 modify_suggest_times::add_suggest_time(),
                 modify_suggest_times::remove_suggest_time(),
                 panel::deploy_panel(),
+                completion::completion_status(),
                 ping_config::set_ping_channel(),
                 ping_config::set_ping_role(),
                 log_config::set_log_channel(),
+                restore_backup::restore_backup(),
+                task_history::task_history(),
+                undo::undo(),
+                trash::trash(),
+                trash::restore_trash(),
+                trash::empty_trash(),
+                trash::set_trash_retention(),
+                export_ics::export_ics(),
+                import_tasks::import_tasks(),
+                export_csv::export_csv(),
+                calendar_feed::create_calendar_feed(),
+                calendar_feed::remove_calendar_feed(),
+                webhooks::add_webhook(),
+                webhooks::test_webhook(),
+                webhooks::remove_webhook(),
             ],
             event_handler: |ctx, event, framework, data| {
                 Box::pin(event_handler(ctx, event, framework, data))
@@ -78,17 +196,51 @@ async fn main() {
         .setup(|ctx, _ready, framework| {
             Box::pin(async move {
                 poise::builtins::register_globally(ctx, &framework.options().commands).await?;
-                Ok(Data::default())
+                Ok(data)
             })
         })
         .build();
 
-    let client = ClientBuilder::new(token, intents)
+    let mut client = ClientBuilder::new(token, intents)
         .framework(framework)
-        .await;
-    client
-        .expect("Failed to create client")
-        .start()
         .await
-        .expect("Failed to start client");
+        .expect("Failed to create client");
+
+    let shard_manager = client.shard_manager.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        println!("Shutting down...");
+        shard_manager.shutdown_all().await;
+    });
+
+    client.start().await.expect("Failed to start client");
+
+    // 終了前に、まだ書き込まれていない変更を保存する
+    saver.abort();
+    flush_data.flush()?;
+
+    Ok(())
+}
+
+/// Ctrl+CかSIGTERM(dockerやsystemdの停止)を待ちます。
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for shutdown signal");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for shutdown signal")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
 }
@@ -0,0 +1,339 @@
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveTime, Weekday};
+
+use crate::recurrence::weekday_label;
+
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// 文章から読み取った日付・時刻と、それ以外の語です。
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ParsedDateTime {
+    pub date: Option<NaiveDate>,
+    pub time: Option<NaiveTime>,
+    pub rest: Vec<String>,
+}
+
+/// 「明日」「来週の金曜」「3/15」「10月20日 1限」「あさって 9:00」のような文章から日付と時刻を読み取ります。
+/// 時刻は「9:00」「午後3時半」のほか、`suggest_times`のラベル(「1限」など)でも指定できます。
+/// 日付と時刻はそれぞれ最初に見つかったものを使い、残りの語は`rest`に入れます。
+pub fn parse_natural_datetime(
+    text: &str,
+    today: NaiveDate,
+    suggest_times: &BTreeMap<NaiveTime, String>,
+) -> ParsedDateTime {
+    let suggest_times = suggest_times
+        .iter()
+        .map(|(time, label)| (*time, normalize(label)))
+        .filter(|(_, label)| !label.is_empty())
+        .collect::<Vec<_>>();
+
+    let mut parsed = ParsedDateTime::default();
+    for word in normalize(text).split_whitespace() {
+        let mut word = word;
+        if parsed.date.is_none() {
+            if let Some((date, rest)) = parse_date_prefix(word, today) {
+                parsed.date = Some(date);
+                word = rest.trim_start_matches('の');
+            }
+        }
+        if parsed.time.is_none() {
+            if let Some((time, rest)) = parse_time_prefix(word, &suggest_times) {
+                parsed.time = Some(time);
+                word = rest;
+            }
+        }
+        if !word.is_empty() {
+            parsed.rest.push(word.to_string());
+        }
+    }
+    parsed
+}
+
+// 全角の数字や記号を半角にする
+fn normalize(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '０'..='９' => char::from_u32(c as u32 - '０' as u32 + '0' as u32).unwrap_or(c),
+            '：' => ':',
+            '／' => '/',
+            '　' => ' ',
+            _ => c,
+        })
+        .collect()
+}
+
+fn take_number(text: &str) -> Option<(u32, &str)> {
+    let end = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    if end == 0 || end > 4 {
+        return None;
+    }
+    Some((text[..end].parse().ok()?, &text[end..]))
+}
+
+// 「金曜」「金曜日」を読み取る。「日本史」などを曜日と間違えないよう「曜」は必須にする
+fn take_weekday(text: &str) -> Option<(Weekday, &str)> {
+    WEEKDAYS.into_iter().find_map(|weekday| {
+        let rest = text.strip_prefix(weekday_label(weekday))?;
+        let rest = rest
+            .strip_prefix("曜日")
+            .or_else(|| rest.strip_prefix('曜'))?;
+        Some((weekday, rest))
+    })
+}
+
+fn parse_date_prefix(word: &str, today: NaiveDate) -> Option<(NaiveDate, &str)> {
+    const RELATIVE_DAYS: [(&str, i64); 9] = [
+        ("今日", 0),
+        ("きょう", 0),
+        ("明日", 1),
+        ("あした", 1),
+        ("あす", 1),
+        ("明後日", 2),
+        ("あさって", 2),
+        ("明々後日", 3),
+        ("しあさって", 3),
+    ];
+    const RELATIVE_WEEKS: [(&str, i64); 3] = [("今週", 0), ("来週", 1), ("再来週", 2)];
+
+    for (prefix, days) in RELATIVE_DAYS {
+        if let Some(rest) = word.strip_prefix(prefix) {
+            return Some((today + Duration::days(days), rest));
+        }
+    }
+
+    // 「来週の金曜」は、来週の月曜から数える
+    for (prefix, weeks) in RELATIVE_WEEKS {
+        if let Some(rest) = word.strip_prefix(prefix) {
+            let (weekday, rest) = take_weekday(rest.trim_start_matches('の'))?;
+            let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+            let date = monday
+                + Duration::weeks(weeks)
+                + Duration::days(weekday.num_days_from_monday() as i64);
+            return Some((date, rest));
+        }
+    }
+
+    // 曜日だけの場合は、明日以降で最も近いその曜日にする
+    if let Some((weekday, rest)) = take_weekday(word) {
+        let days = (weekday.num_days_from_monday() as i64
+            - today.weekday().num_days_from_monday() as i64)
+            .rem_euclid(7);
+        let days = if days == 0 { 7 } else { days };
+        return Some((today + Duration::days(days), rest));
+    }
+
+    let (number, rest) = take_number(word)?;
+
+    // 「3日後」
+    if let Some(rest) = rest.strip_prefix("日後") {
+        return Some((today + Duration::days(number as i64), rest));
+    }
+
+    // 「2025/3/15」「3/15」「2025年3月15日」「3月15日」
+    let (year, month, day, rest) = if let Some(rest) = rest.strip_prefix('/') {
+        let (second, rest) = take_number(rest)?;
+        match rest.strip_prefix('/').and_then(take_number) {
+            Some((third, rest)) => (Some(number as i32), second, third, rest),
+            None => (None, number, second, rest),
+        }
+    } else {
+        let (year, month, rest) = match rest.strip_prefix('年') {
+            Some(rest) => {
+                let (month, rest) = take_number(rest)?;
+                (Some(number as i32), month, rest)
+            }
+            None => (None, number, rest),
+        };
+        let (day, rest) = take_number(rest.strip_prefix('月')?)?;
+        (year, month, day, rest.strip_prefix('日')?)
+    };
+
+    // 年がない場合は、今日以降で最も近い日付にする
+    let date = match year {
+        Some(year) => NaiveDate::from_ymd_opt(year, month, day)?,
+        None => {
+            let date = NaiveDate::from_ymd_opt(today.year(), month, day)?;
+            if date < today {
+                NaiveDate::from_ymd_opt(today.year() + 1, month, day)?
+            } else {
+                date
+            }
+        }
+    };
+    Some((date, rest))
+}
+
+fn parse_time_prefix<'a>(
+    word: &'a str,
+    suggest_times: &[(NaiveTime, String)],
+) -> Option<(NaiveTime, &'a str)> {
+    // 「1限」と「1限目」のようなラベルがある場合は、長いほうを優先する
+    if let Some((time, label)) = suggest_times
+        .iter()
+        .filter(|(_, label)| word.starts_with(label.as_str()))
+        .max_by_key(|(_, label)| label.len())
+    {
+        return Some((*time, &word[label.len()..]));
+    }
+
+    let (afternoon, rest) = match word.strip_prefix("午後") {
+        Some(rest) => (true, rest),
+        None => (false, word.strip_prefix("午前").unwrap_or(word)),
+    };
+    let (hour, rest) = take_number(rest)?;
+    let (minute, rest) = if let Some(rest) = rest.strip_prefix(':') {
+        take_number(rest)?
+    } else if let Some(rest) = rest.strip_prefix('時') {
+        // 「3時間」は時刻ではない
+        if rest.starts_with('間') {
+            return None;
+        }
+        if let Some(rest) = rest.strip_prefix('半') {
+            (30, rest)
+        } else if let Some((minute, rest)) =
+            take_number(rest).and_then(|(minute, rest)| Some((minute, rest.strip_prefix('分')?)))
+        {
+            (minute, rest)
+        } else {
+            (0, rest)
+        }
+    } else {
+        return None;
+    };
+    let hour = if afternoon && hour < 12 {
+        hour + 12
+    } else {
+        hour
+    };
+    Some((NaiveTime::from_hms_opt(hour, minute, 0)?, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{date, time};
+
+    // 2024/10/16(水)
+    fn today() -> NaiveDate {
+        date(2024, 10, 16)
+    }
+
+    fn suggest_times() -> BTreeMap<NaiveTime, String> {
+        BTreeMap::from([
+            (time(8, 50), "1限".to_string()),
+            (time(10, 40), "2限".to_string()),
+            (time(8, 30), "1限前".to_string()),
+        ])
+    }
+
+    fn parse(text: &str) -> ParsedDateTime {
+        parse_natural_datetime(text, today(), &suggest_times())
+    }
+
+    #[test]
+    fn relative_days() {
+        assert_eq!(parse("今日").date, Some(date(2024, 10, 16)));
+        assert_eq!(parse("明日").date, Some(date(2024, 10, 17)));
+        assert_eq!(parse("あさって").date, Some(date(2024, 10, 18)));
+        assert_eq!(parse("しあさって").date, Some(date(2024, 10, 19)));
+        assert_eq!(parse("3日後").date, Some(date(2024, 10, 19)));
+    }
+
+    #[test]
+    fn weekdays() {
+        assert_eq!(parse("来週の金曜").date, Some(date(2024, 10, 25)));
+        assert_eq!(parse("来週金曜日").date, Some(date(2024, 10, 25)));
+        assert_eq!(parse("今週の月曜").date, Some(date(2024, 10, 14)));
+        assert_eq!(parse("再来週の水曜").date, Some(date(2024, 10, 30)));
+        assert_eq!(parse("金曜").date, Some(date(2024, 10, 18)));
+        // 今日と同じ曜日は来週にする
+        assert_eq!(parse("水曜日").date, Some(date(2024, 10, 23)));
+    }
+
+    #[test]
+    fn weekday_requires_suffix() {
+        let parsed = parse("日本史");
+        assert_eq!(parsed.date, None);
+        assert_eq!(parsed.rest, vec!["日本史"]);
+    }
+
+    #[test]
+    fn numeric_dates() {
+        assert_eq!(parse("10/20").date, Some(date(2024, 10, 20)));
+        assert_eq!(parse("10月20日").date, Some(date(2024, 10, 20)));
+        assert_eq!(parse("2025/1/5").date, Some(date(2025, 1, 5)));
+        assert_eq!(parse("2025年1月5日").date, Some(date(2025, 1, 5)));
+        assert_eq!(parse("２０２５／１／５").date, Some(date(2025, 1, 5)));
+        assert_eq!(parse("2/30").date, None);
+    }
+
+    #[test]
+    fn past_dates_roll_over_to_next_year() {
+        assert_eq!(parse("3/15").date, Some(date(2025, 3, 15)));
+        assert_eq!(parse("10/16").date, Some(date(2024, 10, 16)));
+        assert_eq!(parse("10/15").date, Some(date(2025, 10, 15)));
+    }
+
+    #[test]
+    fn clock_times() {
+        assert_eq!(parse("9:00").time, Some(time(9, 0)));
+        assert_eq!(parse("9:05").time, Some(time(9, 5)));
+        assert_eq!(parse("15時").time, Some(time(15, 0)));
+        assert_eq!(parse("15時20分").time, Some(time(15, 20)));
+        assert_eq!(parse("午後3時半").time, Some(time(15, 30)));
+        assert_eq!(parse("午前9時").time, Some(time(9, 0)));
+        assert_eq!(parse("9：30").time, Some(time(9, 30)));
+        assert_eq!(parse("25:00").time, None);
+    }
+
+    #[test]
+    fn durations_are_not_times() {
+        let parsed = parse("3時間 勉強");
+        assert_eq!(parsed.time, None);
+        assert_eq!(parsed.rest, vec!["3時間", "勉強"]);
+    }
+
+    #[test]
+    fn suggest_time_labels() {
+        assert_eq!(parse("1限").time, Some(time(8, 50)));
+        assert_eq!(parse("２限").time, Some(time(10, 40)));
+        // 「1限」と「1限前」では長いほうを使う
+        assert_eq!(parse("1限前").time, Some(time(8, 30)));
+    }
+
+    #[test]
+    fn date_and_time_together() {
+        let parsed = parse("10月20日 1限 化学 レポート");
+        assert_eq!(parsed.date, Some(date(2024, 10, 20)));
+        assert_eq!(parsed.time, Some(time(8, 50)));
+        assert_eq!(parsed.rest, vec!["化学", "レポート"]);
+
+        let parsed = parse("あさって 9:00");
+        assert_eq!(parsed.date, Some(date(2024, 10, 18)));
+        assert_eq!(parsed.time, Some(time(9, 0)));
+        assert!(parsed.rest.is_empty());
+
+        let parsed = parse("明日9:00宿題");
+        assert_eq!(parsed.date, Some(date(2024, 10, 17)));
+        assert_eq!(parsed.time, Some(time(9, 0)));
+        assert_eq!(parsed.rest, vec!["宿題"]);
+    }
+
+    #[test]
+    fn only_first_date_is_used() {
+        let parsed = parse("明日 3/15");
+        assert_eq!(parsed.date, Some(date(2024, 10, 17)));
+        assert_eq!(parsed.rest, vec!["3/15"]);
+    }
+}
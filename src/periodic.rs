@@ -1,15 +1,20 @@
+use std::ops::Bound;
+
 use anyhow::{Context as _, Error, Ok};
 use chrono::{Duration, Local, NaiveTime};
-use itertools::Itertools;
 use poise::serenity_prelude::*;
-use tokio::{
-    fs::File,
-    time::{sleep_until, Instant},
+use tokio::time::{sleep_until, Instant};
+
+use crate::{
+    commands::completion::complete_buttons,
+    data::{Data, GuildData},
+    storage,
+    utilities::format_datetime,
 };
 
-use crate::{data, utilities::format_datetime};
+const MAX_EMBEDS: usize = 10;
 
-pub async fn wait(ctx: Context) {
+pub async fn wait(ctx: Context, data: Data) {
     loop {
         let now = Local::now();
         let target_time = {
@@ -29,16 +34,34 @@ pub async fn wait(ctx: Context) {
         println!("Sleeping for {} seconds", sleep_duration.num_seconds());
 
         sleep_until(Instant::now() + sleep_duration.to_std().unwrap()).await;
-        notify(ctx.clone()).await.expect("Failed to notify");
-        backup(ctx.clone()).await.expect("Failed to backup");
+        purge_trash(&data);
+        // 設定が済んでいないギルドがあっても、他のギルドの処理は続ける
+        let guilds = data.guilds.lock().unwrap().clone();
+        for (guild_id, guild_data) in guilds {
+            if let Err(e) = notify(ctx.clone(), &guild_data).await {
+                println!("Failed to notify {}: {:?}", guild_id, e);
+            }
+            if let Err(e) = backup(ctx.clone(), &guild_data).await {
+                println!("Failed to backup {}: {:?}", guild_id, e);
+            }
+        }
+    }
+}
+
+fn purge_trash(data: &Data) {
+    let guilds = data.guilds.lock().unwrap().clone();
+    for (guild_id, guild_data) in guilds {
+        let purged = guild_data.purge_trash(Local::now());
+        if purged > 0 {
+            println!("Purged {} trash entries of {}", purged, guild_id);
+            data.request_save(guild_id);
+        }
     }
 }
 
-async fn notify(ctx: Context) -> Result<(), Error> {
-    let data = data::load()?;
+async fn notify(ctx: Context, data: &GuildData) -> Result<(), Error> {
     let ping_channel = (*data.ping_channel.lock().unwrap()).context("Ping channel not set")?;
     let ping_role = (*data.ping_role.lock().unwrap()).context("Ping role not set")?;
-    let tasks = data.tasks.lock().unwrap().clone();
 
     let from = (Local::now() + Duration::days(1))
         .with_time(NaiveTime::from_hms_opt(0, 0, 0).unwrap())
@@ -49,45 +72,77 @@ async fn notify(ctx: Context) -> Result<(), Error> {
 
     println!("Searching tasks: from {} to {}", from, to);
 
-    let fields = tasks
-        .iter()
-        .filter(|task| from < task.datetime && task.datetime <= to)
-        .sorted_by_key(|task| task.datetime)
-        .map(|task| task.to_field());
-
-    if fields.len() > 0 {
-        ping_channel
-            .send_message(
-                ctx,
-                CreateMessage::new()
-                    .content(format!("{}", ping_role.mention()))
-                    .embed(
-                        CreateEmbed::default()
-                            .title("タスク通知")
-                            .description("明日のタスクをお知らせします！")
-                            .fields(fields)
-                            .color(Color::RED),
-                    ),
-            )
-            .await?;
+    // 終日のタスクと複数日にわたるタスクは、明日と重なっているものを含める
+    let tasks = data
+        .tasks_in((Bound::Included(from), Bound::Included(to)), None)
+        .into_iter()
+        .filter(|task| match (task.all_day, task.end) {
+            (false, None) => task.datetime > from,
+            _ => task.datetime < to && task.end_datetime() > from,
+        })
+        .collect::<Vec<_>>();
+    if tasks.is_empty() {
+        return Ok(());
     }
+
+    // カテゴリーの並び順でまとめて、カテゴリーごとにその色の埋め込みにする
+    let categories = data.categories.lock().unwrap().clone();
+    let mut sorted = tasks.clone();
+    sorted.sort_by_key(|task| {
+        categories
+            .iter()
+            .position(|c| c.name == task.category.0)
+            .unwrap_or(categories.len())
+    });
+    let groups = sorted
+        .chunk_by(|a, b| a.category == b.category)
+        .collect::<Vec<_>>();
+    // 1つのメッセージに付けられる埋め込みは10個まで
+    let embeds = if groups.len() < MAX_EMBEDS {
+        groups
+            .iter()
+            .map(|group| {
+                let info = data.category_info(&group[0].category);
+                CreateEmbed::default()
+                    .title(info.label())
+                    .fields(group.iter().map(|task| data.task_field(task)))
+                    .color(info.color.map_or(Color::RED, Color::new))
+            })
+            .collect()
+    } else {
+        vec![CreateEmbed::default()
+            .fields(tasks.iter().map(|task| data.task_field(task)))
+            .color(Color::RED)]
+    };
+
+    ping_channel
+        .send_message(
+            ctx,
+            CreateMessage::new()
+                .content(format!("{}", ping_role.mention()))
+                .embed(
+                    CreateEmbed::default()
+                        .title("タスク通知")
+                        .description("明日のタスクをお知らせします！")
+                        .color(Color::RED),
+                )
+                .add_embeds(embeds)
+                .components(complete_buttons(&tasks)),
+        )
+        .await?;
     Ok(())
 }
 
-async fn backup(ctx: Context) -> Result<(), Error> {
-    let data = data::load()?;
+async fn backup(ctx: Context, data: &GuildData) -> Result<(), Error> {
     let log_channel = (*data.log_channel.lock().unwrap()).context("Log channel not set")?;
 
     log_channel
         .send_files(
             ctx,
-            vec![
-                CreateAttachment::file(
-                    &File::open(data::FILE_PATH).await?,
-                    format!("{}.json", Local::now().timestamp()),
-                )
-                .await?,
-            ],
+            vec![CreateAttachment::bytes(
+                serde_json::to_vec(&storage::migration::to_backup_value(data)?)?,
+                format!("{}.json", Local::now().timestamp()),
+            )],
             CreateMessage::default().embed(CreateEmbed::default().title(format!(
                 "データのバックアップ ({})",
                 format_datetime(Local::now())
@@ -0,0 +1,269 @@
+use std::collections::BTreeSet;
+
+use anyhow::{Context as _, Error};
+use chrono::{Datelike, Duration, Months, NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+
+use crate::utilities::format_date;
+
+// 終わりのない繰り返しにならないように、1つの繰り返しから作る回数の上限
+const MAX_OCCURRENCES: usize = 1000;
+
+/// タスクの繰り返し
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Recurrence {
+    pub rule: RecurrenceRule,
+    pub end: RecurrenceEnd,
+    // 「この回のみ」編集された回の日付。繰り返しからは除かれる
+    #[serde(default)]
+    pub exceptions: BTreeSet<NaiveDate>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum RecurrenceRule {
+    // 毎週、指定した曜日
+    Weekly(Vec<Weekday>),
+    // N日ごと
+    Daily(u32),
+    // 毎月、指定した日 (その日がない月は飛ばす)
+    Monthly(u32),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecurrenceEnd {
+    // この日まで
+    Until(NaiveDate),
+    // 最初の回を含めた回数
+    Count(u32),
+}
+
+impl Recurrence {
+    /// `start`から始まる繰り返しの日付を順に返します。除外された回は含みません。
+    pub fn dates(&self, start: NaiveDate) -> impl Iterator<Item = NaiveDate> + '_ {
+        let candidates: Box<dyn Iterator<Item = NaiveDate>> = match &self.rule {
+            RecurrenceRule::Weekly(weekdays) => {
+                let weekdays = if weekdays.is_empty() {
+                    vec![start.weekday()]
+                } else {
+                    weekdays.clone()
+                };
+                Box::new(
+                    start
+                        .iter_days()
+                        .filter(move |date| weekdays.contains(&date.weekday())),
+                )
+            }
+            RecurrenceRule::Daily(interval) => {
+                let interval = Duration::days((*interval).max(1) as i64);
+                Box::new(std::iter::successors(Some(start), move |date| {
+                    date.checked_add_signed(interval)
+                }))
+            }
+            RecurrenceRule::Monthly(day) => {
+                let day = *day;
+                let first = start.with_day(1).unwrap();
+                Box::new(
+                    (0..)
+                        .map_while(move |i| first.checked_add_months(Months::new(i)))
+                        .filter_map(move |month| month.with_day(day))
+                        .filter(move |date| *date >= start),
+                )
+            }
+        };
+
+        let count = match self.end {
+            RecurrenceEnd::Count(count) => (count as usize).min(MAX_OCCURRENCES),
+            RecurrenceEnd::Until(_) => MAX_OCCURRENCES,
+        };
+        let until = match self.end {
+            RecurrenceEnd::Until(until) => Some(until),
+            RecurrenceEnd::Count(_) => None,
+        };
+        candidates
+            .take_while(move |date| until.is_none_or(|until| *date <= until))
+            .take(count)
+            .filter(|date| !self.exceptions.contains(date))
+    }
+
+    pub fn label(&self) -> String {
+        let rule = match &self.rule {
+            RecurrenceRule::Weekly(weekdays) => format!(
+                "毎週{}曜日",
+                weekdays
+                    .iter()
+                    .map(|w| weekday_label(*w))
+                    .collect::<Vec<_>>()
+                    .join("・")
+            ),
+            RecurrenceRule::Daily(1) => "毎日".into(),
+            RecurrenceRule::Daily(interval) => format!("{}日ごと", interval),
+            RecurrenceRule::Monthly(day) => format!("毎月{}日", day),
+        };
+        let end = match self.end {
+            RecurrenceEnd::Until(until) => format!("{}まで", format_date(until)),
+            RecurrenceEnd::Count(count) => format!("{}回", count),
+        };
+        format!("{} ({})", rule, end)
+    }
+}
+
+pub fn weekday_label(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "月",
+        Weekday::Tue => "火",
+        Weekday::Wed => "水",
+        Weekday::Thu => "木",
+        Weekday::Fri => "金",
+        Weekday::Sat => "土",
+        Weekday::Sun => "日",
+    }
+}
+
+/// 「月,水,金」のような曜日の一覧を読み取ります。
+pub fn parse_weekdays(text: &str) -> Result<Vec<Weekday>, Error> {
+    let mut weekdays = text
+        .split([',', '、', '・', ' '])
+        .map(|w| w.trim().trim_end_matches("曜日").trim_end_matches('曜'))
+        .filter(|w| !w.is_empty())
+        .map(|w| {
+            [
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri,
+                Weekday::Sat,
+                Weekday::Sun,
+            ]
+            .into_iter()
+            .find(|weekday| weekday_label(*weekday) == w || w.parse() == Ok(*weekday))
+            .with_context(|| format!("曜日を読み取れませんでした: {}", w))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    weekdays.sort_by_key(|w| w.num_days_from_monday());
+    weekdays.dedup();
+    Ok(weekdays)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::date;
+
+    fn recurrence(rule: RecurrenceRule, end: RecurrenceEnd) -> Recurrence {
+        Recurrence {
+            rule,
+            end,
+            exceptions: BTreeSet::new(),
+        }
+    }
+
+    fn dates(recurrence: &Recurrence, start: NaiveDate) -> Vec<NaiveDate> {
+        recurrence.dates(start).collect()
+    }
+
+    #[test]
+    fn weekly() {
+        // 2024/10/14(月)から
+        let r = recurrence(
+            RecurrenceRule::Weekly(vec![Weekday::Mon, Weekday::Thu]),
+            RecurrenceEnd::Count(4),
+        );
+        assert_eq!(
+            dates(&r, date(2024, 10, 14)),
+            [
+                date(2024, 10, 14),
+                date(2024, 10, 17),
+                date(2024, 10, 21),
+                date(2024, 10, 24)
+            ]
+        );
+    }
+
+    #[test]
+    fn weekly_without_weekdays_uses_start() {
+        let r = recurrence(RecurrenceRule::Weekly(vec![]), RecurrenceEnd::Count(3));
+        assert_eq!(
+            dates(&r, date(2024, 10, 16)),
+            [date(2024, 10, 16), date(2024, 10, 23), date(2024, 10, 30)]
+        );
+    }
+
+    #[test]
+    fn daily_with_interval() {
+        let r = recurrence(
+            RecurrenceRule::Daily(3),
+            RecurrenceEnd::Until(date(2024, 11, 5)),
+        );
+        assert_eq!(
+            dates(&r, date(2024, 10, 28)),
+            [date(2024, 10, 28), date(2024, 10, 31), date(2024, 11, 3)]
+        );
+    }
+
+    #[test]
+    fn daily_zero_interval_is_every_day() {
+        let r = recurrence(RecurrenceRule::Daily(0), RecurrenceEnd::Count(2));
+        assert_eq!(
+            dates(&r, date(2024, 12, 31)),
+            [date(2024, 12, 31), date(2025, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn monthly_skips_missing_days() {
+        let r = recurrence(RecurrenceRule::Monthly(31), RecurrenceEnd::Count(3));
+        assert_eq!(
+            dates(&r, date(2024, 1, 31)),
+            [date(2024, 1, 31), date(2024, 3, 31), date(2024, 5, 31)]
+        );
+    }
+
+    #[test]
+    fn monthly_starts_after_start() {
+        let r = recurrence(
+            RecurrenceRule::Monthly(10),
+            RecurrenceEnd::Until(date(2024, 12, 10)),
+        );
+        assert_eq!(
+            dates(&r, date(2024, 10, 16)),
+            [date(2024, 11, 10), date(2024, 12, 10)]
+        );
+    }
+
+    #[test]
+    fn until_is_inclusive() {
+        let r = recurrence(
+            RecurrenceRule::Daily(1),
+            RecurrenceEnd::Until(date(2024, 10, 16)),
+        );
+        assert_eq!(dates(&r, date(2024, 10, 16)), [date(2024, 10, 16)]);
+        assert!(dates(&r, date(2024, 10, 17)).is_empty());
+    }
+
+    #[test]
+    fn count_is_capped() {
+        let r = recurrence(RecurrenceRule::Daily(1), RecurrenceEnd::Count(u32::MAX));
+        assert_eq!(r.dates(date(2024, 1, 1)).count(), MAX_OCCURRENCES);
+    }
+
+    #[test]
+    fn exceptions_are_skipped_but_counted() {
+        let mut r = recurrence(RecurrenceRule::Daily(1), RecurrenceEnd::Count(3));
+        r.exceptions.insert(date(2024, 10, 17));
+        assert_eq!(
+            dates(&r, date(2024, 10, 16)),
+            [date(2024, 10, 16), date(2024, 10, 18)]
+        );
+    }
+
+    #[test]
+    fn parses_weekdays() {
+        assert_eq!(
+            parse_weekdays("金,月曜、水曜日・月").unwrap(),
+            [Weekday::Mon, Weekday::Wed, Weekday::Fri]
+        );
+        assert_eq!(parse_weekdays("tue").unwrap(), [Weekday::Tue]);
+        assert!(parse_weekdays("月,祝").is_err());
+    }
+}
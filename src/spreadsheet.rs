@@ -0,0 +1,242 @@
+use anyhow::{Context as _, Error};
+
+use crate::{Subject, Task};
+
+/// 書き出すCSVの列
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Category,
+    Subject,
+    Details,
+    DateTime,
+    Creator,
+}
+
+impl Column {
+    pub const VALUES: [Column; 5] = [
+        Column::Category,
+        Column::Subject,
+        Column::Details,
+        Column::DateTime,
+        Column::Creator,
+    ];
+
+    // 読み込むときにも使えるように、ヘッダーは読み込みで判別できる名前にする
+    pub fn label(&self) -> &'static str {
+        match self {
+            Column::Category => "カテゴリー",
+            Column::Subject => "教科",
+            Column::Details => "内容",
+            Column::DateTime => "日時",
+            Column::Creator => "作成者",
+        }
+    }
+
+    fn english_label(&self) -> &'static str {
+        match self {
+            Column::Category => "category",
+            Column::Subject => "subject",
+            Column::Details => "details",
+            Column::DateTime => "datetime",
+            Column::Creator => "creator",
+        }
+    }
+
+    /// カンマ区切りの列名を読み取ります。
+    pub fn parse_list(text: &str) -> Result<Vec<Column>, Error> {
+        text.split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                Column::VALUES
+                    .into_iter()
+                    .find(|c| c.label() == name || c.english_label().eq_ignore_ascii_case(name))
+                    .with_context(|| format!("不明な列です: {}", name))
+            })
+            .collect()
+    }
+}
+
+/// タスクをCSVで書き出します。作成者は`creator`で名前にします。
+pub fn render<'a>(
+    tasks: impl IntoIterator<Item = &'a Task>,
+    columns: &[Column],
+    creator: impl Fn(&Task) -> String,
+) -> Result<Vec<u8>, Error> {
+    // BOMを付けないとExcelで文字化けする
+    let mut writer = csv::Writer::from_writer(b"\xEF\xBB\xBF".to_vec());
+    writer.write_record(columns.iter().map(|c| c.label()))?;
+    for task in tasks {
+        writer.write_record(columns.iter().map(|column| match column {
+            Column::Category => task.category.to_string(),
+            Column::Subject => match &task.subject {
+                Subject::Set(subject) => subject.clone(),
+                Subject::Unset => "".into(),
+            },
+            Column::Details => task.details.clone(),
+            // 終日のタスクは日付のみ書き出す
+            Column::DateTime if task.all_day => task.datetime.format("%Y-%m-%d").to_string(),
+            Column::DateTime => task.datetime.to_rfc3339(),
+            Column::Creator => creator(task),
+        }))?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+/// CSVファイルの1行分の項目
+#[derive(Debug, Clone, Default)]
+pub struct CsvRow {
+    // ヘッダーを1行目とした行番号
+    pub line: u64,
+    pub id: Option<String>,
+    pub category: Option<String>,
+    pub subject: Option<String>,
+    pub details: Option<String>,
+    pub datetime: Option<String>,
+    pub date: Option<String>,
+    pub time: Option<String>,
+}
+
+/// ヘッダー付きのCSVを読み込みます。列はヘッダーの名前で判別します。
+pub fn parse(bytes: &[u8]) -> Result<Vec<CsvRow>, Error> {
+    // Excelで保存したファイルにはBOMが付いていることがある
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(bytes);
+
+    let headers = reader.headers().context("Failed to read header")?.clone();
+    let column = |names: &[&str]| {
+        headers
+            .iter()
+            .position(|h| names.iter().any(|name| h.trim().eq_ignore_ascii_case(name)))
+    };
+    let id = column(&["id", "uuid"]);
+    let category = column(&["category", "カテゴリー", "カテゴリ"]);
+    let subject = column(&["subject", "教科", "科目"]);
+    let details = column(&["details", "内容", "詳細"]);
+    let datetime = column(&["datetime", "日時"]);
+    let date = column(&["date", "日付"]);
+    let time = column(&["time", "時刻", "時間"]);
+    anyhow::ensure!(details.is_some(), "内容の列が見つかりませんでした");
+    anyhow::ensure!(
+        datetime.is_some() || date.is_some(),
+        "日時または日付の列が見つかりませんでした"
+    );
+
+    let mut rows = vec![];
+    for (i, record) in reader.records().enumerate() {
+        let record = record.with_context(|| format!("{}行目を読み取れませんでした", i + 2))?;
+        let field = |column: Option<usize>| {
+            column
+                .and_then(|c| record.get(c))
+                .map(|f| f.trim().to_string())
+                .filter(|f| !f.is_empty())
+        };
+        // 空行は読み飛ばす
+        if record.iter().all(|f| f.trim().is_empty()) {
+            continue;
+        }
+        rows.push(CsvRow {
+            line: record
+                .position()
+                .map_or(i as u64 + 2, |p| line_number(bytes, p)),
+            id: field(id),
+            category: field(category),
+            subject: field(subject),
+            details: field(details),
+            datetime: field(datetime),
+            date: field(date),
+            time: field(time),
+        });
+    }
+    Ok(rows)
+}
+
+// csvの行番号は読み飛ばした空行を数えず、位置も空行の先頭を指すので、
+// 空行を飛ばした位置までの改行を数える
+fn line_number(bytes: &[u8], position: &csv::Position) -> u64 {
+    let start = position.byte() as usize;
+    let blank = bytes[start..]
+        .iter()
+        .take_while(|b| matches!(b, b'\r' | b'\n'))
+        .count();
+    bytes[..start + blank]
+        .iter()
+        .filter(|b| **b == b'\n')
+        .count() as u64
+        + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{datetime, task};
+
+    #[test]
+    fn parses_japanese_headers_with_bom() {
+        let csv = "\u{FEFF}カテゴリー,教科,内容,日付,時刻\n宿題,数学,ワーク,2024/10/18,8:50\n";
+        let rows = parse(csv.as_bytes()).unwrap();
+        assert_eq!(rows.len(), 1);
+        let row = &rows[0];
+        assert_eq!(row.line, 2);
+        assert_eq!(row.category.as_deref(), Some("宿題"));
+        assert_eq!(row.subject.as_deref(), Some("数学"));
+        assert_eq!(row.details.as_deref(), Some("ワーク"));
+        assert_eq!(row.date.as_deref(), Some("2024/10/18"));
+        assert_eq!(row.time.as_deref(), Some("8:50"));
+        assert_eq!(row.datetime, None);
+    }
+
+    #[test]
+    fn parses_english_headers_in_any_order() {
+        let csv = "Datetime, Details ,UUID\n2024-10-18T08:50:00+09:00,  小テスト  ,abc\n";
+        let rows = parse(csv.as_bytes()).unwrap();
+        assert_eq!(
+            rows[0].datetime.as_deref(),
+            Some("2024-10-18T08:50:00+09:00")
+        );
+        assert_eq!(rows[0].details.as_deref(), Some("小テスト"));
+        assert_eq!(rows[0].id.as_deref(), Some("abc"));
+        assert_eq!(rows[0].category, None);
+    }
+
+    #[test]
+    fn skips_blank_rows_and_keeps_line_numbers() {
+        let csv = "内容,日付\nA,2024/10/18\n,\n\nB\n";
+        let rows = parse(csv.as_bytes()).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].line, 5);
+        assert_eq!(rows[1].details.as_deref(), Some("B"));
+        // 列が足りない行は空として扱う
+        assert_eq!(rows[1].date, None);
+    }
+
+    #[test]
+    fn requires_details_and_date_columns() {
+        assert!(parse("日付\n2024/10/18\n".as_bytes()).is_err());
+        assert!(parse("内容\nワーク\n".as_bytes()).is_err());
+        assert!(parse(b"").is_err());
+    }
+
+    #[test]
+    fn reads_rendered_csv() {
+        let task = Task {
+            datetime: datetime(2024, 10, 18, 0, 0),
+            all_day: true,
+            ..task("ワーク, p.10")
+        };
+        let bytes = render([&task], &Column::VALUES, |_| "先生".into()).unwrap();
+        let rows = parse(&bytes).unwrap();
+        assert_eq!(rows[0].category.as_deref(), Some("宿題"));
+        assert_eq!(rows[0].details.as_deref(), Some("ワーク, p.10"));
+        assert_eq!(rows[0].datetime.as_deref(), Some("2024-10-18"));
+    }
+
+    #[test]
+    fn parses_column_list() {
+        assert_eq!(
+            Column::parse_list("内容, DateTime,").unwrap(),
+            [Column::Details, Column::DateTime]
+        );
+        assert!(Column::parse_list("内容,備考").is_err());
+    }
+}
@@ -0,0 +1,96 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Error;
+use poise::serenity_prelude::*;
+
+use super::{backup_path, migration, Storage};
+use crate::data::GuildData;
+
+pub const DIR_PATH: &str = "data";
+
+/// ギルドごとに`data/<ギルドID>.json`へ保存します。
+#[derive(Debug)]
+pub struct JsonStorage;
+
+impl JsonStorage {
+    pub fn file_path(guild_id: GuildId) -> PathBuf {
+        Path::new(DIR_PATH).join(format!("{}.json", guild_id))
+    }
+
+    /// ファイルを読み込み、必要であれば現在の形式に変換します。
+    /// 失敗した場合は元のファイルを`.bak`として残します。
+    pub fn load_file(path: impl AsRef<Path>) -> Result<GuildData, Error> {
+        let path = path.as_ref();
+        let data = fs::read_to_string(path)?;
+        serde_json::from_str(&data)
+            .map_err(Error::from)
+            .and_then(migration::from_value)
+            .or_else(|e| {
+                let backup = backup_path(path);
+                fs::copy(path, &backup)?;
+                Err(e.context(format!(
+                    "Failed to load {:?} (the original file is kept as {:?})",
+                    path, backup
+                )))
+            })
+    }
+}
+
+/// 一時ファイルに書き込んでから置き換えることで、
+/// 書き込み中に落ちても元のファイルが壊れないようにします。
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+
+    let mut file = File::create(&temp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&temp_path, path)?;
+
+    // 置き換えたこと自体を永続化するため、ディレクトリも同期する
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+impl Storage for JsonStorage {
+    fn save(&self, guild_id: GuildId, data: &GuildData) -> Result<(), Error> {
+        let data = migration::to_value(data)?.to_string();
+        fs::create_dir_all(DIR_PATH)?;
+        write_atomic(&Self::file_path(guild_id), data.as_bytes())
+    }
+
+    fn load(&self, guild_id: GuildId) -> Result<GuildData, Error> {
+        Self::load_file(Self::file_path(guild_id))
+    }
+
+    fn load_all(&self) -> Result<BTreeMap<GuildId, GuildData>, Error> {
+        let mut guilds = BTreeMap::new();
+        if !Path::new(DIR_PATH).exists() {
+            return Ok(guilds);
+        }
+        for entry in fs::read_dir(DIR_PATH)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                let Some(guild_id) = path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .and_then(|s| s.parse::<u64>().ok())
+                else {
+                    continue;
+                };
+                let guild_id = GuildId::new(guild_id);
+                guilds.insert(guild_id, self.load(guild_id)?);
+            }
+        }
+        Ok(guilds)
+    }
+}
@@ -0,0 +1,395 @@
+use anyhow::{Context as _, Error};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::data::GuildData;
+
+/// 現在の保存形式のバージョン
+pub const VERSION: u64 = 8;
+
+type Migration = fn(&mut Value) -> Result<(), Error>;
+
+// MIGRATIONS[n] はバージョン n のデータをバージョン n + 1 に変換する
+const MIGRATIONS: [Migration; VERSION as usize] = [
+    v0_to_v1, v1_to_v2, v2_to_v3, v3_to_v4, v4_to_v5, v5_to_v6, v6_to_v7, v7_to_v8,
+];
+
+// v0: versionフィールドがなく、タスクがIDを持たない場合がある
+fn v0_to_v1(data: &mut Value) -> Result<(), Error> {
+    for task in tasks_mut(data)? {
+        let task = task.as_object_mut().context("Task is not an object")?;
+        if !task.contains_key("id") {
+            task.insert("id".into(), Uuid::new_v4().to_string().into());
+        }
+    }
+    Ok(())
+}
+
+// v1: タスクの変更履歴を持たない
+fn v1_to_v2(data: &mut Value) -> Result<(), Error> {
+    object_mut(data)?.insert("history".into(), Value::Array(vec![]));
+    Ok(())
+}
+
+// v2: ゴミ箱がなく、削除した項目はすぐに消えていた
+fn v2_to_v3(data: &mut Value) -> Result<(), Error> {
+    let data = object_mut(data)?;
+    data.insert("trash".into(), Value::Array(vec![]));
+    data.insert("trash_retention_days".into(), Value::Null);
+    Ok(())
+}
+
+// v3: 購読用カレンダーのURLがない
+fn v3_to_v4(data: &mut Value) -> Result<(), Error> {
+    object_mut(data)?.insert("calendar_feeds".into(), Value::Array(vec![]));
+    Ok(())
+}
+
+// v4: 変更を通知するWebhookがない
+fn v4_to_v5(data: &mut Value) -> Result<(), Error> {
+    object_mut(data)?.insert("webhooks".into(), Value::Array(vec![]));
+    Ok(())
+}
+
+// v5: ユーザーごとのタスクの完了を記録していない
+fn v5_to_v6(data: &mut Value) -> Result<(), Error> {
+    object_mut(data)?.insert("completions".into(), Value::Array(vec![]));
+    Ok(())
+}
+
+// v6: カテゴリーは固定の列挙型で、タスクには英語の名前で記録していた
+fn v6_to_v7(data: &mut Value) -> Result<(), Error> {
+    const LABELS: [(&str, &str); 5] = [
+        ("Event", "イベント"),
+        ("Exam", "テスト"),
+        ("Homework", "宿題"),
+        ("Belongings", "持ち物"),
+        ("Other", "その他"),
+    ];
+    let rename = |category: &mut Value| {
+        if let Some((_, label)) = LABELS.iter().find(|(name, _)| category == name) {
+            *category = (*label).into();
+        }
+    };
+    let rename_task = |task: &mut Value| {
+        if let Some(category) = task.get_mut("category") {
+            rename(category);
+        }
+    };
+
+    for task in tasks_mut(data)? {
+        rename_task(task);
+    }
+    let data = object_mut(data)?;
+    // 履歴やゴミ箱に残っているタスクも、元に戻したときに困らないように書き換える
+    for event in data
+        .get_mut("history")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+    {
+        for key in ["before", "after"] {
+            if let Some(task) = event.get_mut(key) {
+                rename_task(task);
+            }
+        }
+    }
+    for entry in data
+        .get_mut("trash")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+    {
+        if let Some(task) = entry.pointer_mut("/item/Task") {
+            rename_task(task);
+        }
+    }
+    for feed in data
+        .get_mut("calendar_feeds")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+    {
+        if let Some(category) = feed.get_mut("category") {
+            rename(category);
+        }
+    }
+
+    data.insert(
+        "categories".into(),
+        LABELS
+            .iter()
+            .map(|(_, label)| serde_json::json!({ "name": label, "emoji": null, "color": null }))
+            .collect(),
+    );
+    Ok(())
+}
+
+// v7: 教科は名前のみで、担当や色などを持たない
+fn v7_to_v8(data: &mut Value) -> Result<(), Error> {
+    let subject = |name: &Value| {
+        serde_json::json!({
+            "name": name,
+            "teacher": null,
+            "color": null,
+            "emoji": null,
+            "short_code": null,
+            "aliases": [],
+        })
+    };
+
+    let data = object_mut(data)?;
+    let subjects = data
+        .get_mut("subjects")
+        .and_then(Value::as_array_mut)
+        .context("Missing subjects")?;
+    *subjects = subjects.iter().map(subject).collect();
+    for entry in data
+        .get_mut("trash")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+    {
+        if let Some(name) = entry.pointer_mut("/item/Subject") {
+            *name = subject(name);
+        }
+    }
+    Ok(())
+}
+
+fn object_mut(data: &mut Value) -> Result<&mut serde_json::Map<String, Value>, Error> {
+    data.as_object_mut().context("Data is not an object")
+}
+
+fn tasks_mut(data: &mut Value) -> Result<&mut Vec<Value>, Error> {
+    data.get_mut("tasks")
+        .and_then(Value::as_array_mut)
+        .context("Missing tasks")
+}
+
+/// 保存されたデータを現在のバージョンまで変換します。
+pub fn migrate(mut data: Value) -> Result<Value, Error> {
+    let version = match data.get("version") {
+        Some(version) => version.as_u64().context("Invalid version")?,
+        None => 0,
+    };
+    anyhow::ensure!(
+        version <= VERSION,
+        "Data version {} is newer than supported version {}",
+        version,
+        VERSION
+    );
+
+    for (from, step) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        step(&mut data)
+            .with_context(|| format!("Failed to migrate data from v{} to v{}", from, from + 1))?;
+        object_mut(&mut data)?.insert("version".into(), (from as u64 + 1).into());
+    }
+    Ok(data)
+}
+
+pub fn to_value(data: &GuildData) -> Result<Value, Error> {
+    let mut value = serde_json::to_value(data)?;
+    object_mut(&mut value)?.insert("version".into(), VERSION.into());
+    Ok(value)
+}
+
+/// ログチャンネルに投稿するバックアップ用に変換します。
+/// チャンネルを読める人に署名を偽造されたり購読用URLを使われたりしないよう、Webhookと購読用URLは含めません。
+pub fn to_backup_value(data: &GuildData) -> Result<Value, Error> {
+    let mut value = to_value(data)?;
+    let object = object_mut(&mut value)?;
+    object.insert("webhooks".into(), Value::Array(vec![]));
+    object.insert("calendar_feeds".into(), Value::Array(vec![]));
+    Ok(value)
+}
+
+pub fn from_value(value: Value) -> Result<GuildData, Error> {
+    let value = migrate(value)?;
+    serde_json::from_value(value).context("Failed to parse migrated data")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use poise::serenity_prelude::UserId;
+
+    use crate::{fixtures::task, webhook::WebhookEndpoint, Category, Task};
+
+    const TASK_ID: &str = "6c5e7c3a-1f0b-4a55-9d2e-5b8e8f0f6a01";
+
+    // バージョン0の頃に保存されていたデータ
+    fn v0_fixture() -> Value {
+        json!({
+            "tasks": [
+                {
+                    "category": "Homework",
+                    "subject": "数学",
+                    "details": "ワーク",
+                    "datetime": "2024-10-18T08:50:00+09:00",
+                    "id": TASK_ID,
+                },
+                {
+                    "category": "Exam",
+                    "subject": null,
+                    "details": "小テスト",
+                    "datetime": "2024-10-21T00:00:00+09:00",
+                },
+            ],
+            "subjects": ["数学", "英語"],
+            "suggest_times": { "08:50:00": "1限" },
+            "panel_message": null,
+            "ping_channel": null,
+            "ping_role": null,
+            "log_channel": null,
+        })
+    }
+
+    // v6以降のタスクは今と同じ形で保存されている
+    fn task_value(category: &str) -> Value {
+        serde_json::to_value(Task {
+            category: Category(category.into()),
+            ..task("ワーク")
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn v0_to_v1_adds_missing_ids() {
+        let mut data = v0_fixture();
+        v0_to_v1(&mut data).unwrap();
+        let tasks = data["tasks"].as_array().unwrap();
+        assert_eq!(tasks[0]["id"], TASK_ID);
+        assert!(tasks[1]["id"].as_str().unwrap().parse::<Uuid>().is_ok());
+    }
+
+    #[test]
+    fn v1_to_v2_adds_history() {
+        let mut data = json!({ "tasks": [] });
+        v1_to_v2(&mut data).unwrap();
+        assert_eq!(data["history"], json!([]));
+    }
+
+    #[test]
+    fn v2_to_v3_adds_trash() {
+        let mut data = json!({ "tasks": [] });
+        v2_to_v3(&mut data).unwrap();
+        assert_eq!(data["trash"], json!([]));
+        assert_eq!(data["trash_retention_days"], Value::Null);
+    }
+
+    #[test]
+    fn v3_to_v4_adds_calendar_feeds() {
+        let mut data = json!({ "tasks": [] });
+        v3_to_v4(&mut data).unwrap();
+        assert_eq!(data["calendar_feeds"], json!([]));
+    }
+
+    #[test]
+    fn v4_to_v5_adds_webhooks() {
+        let mut data = json!({ "tasks": [] });
+        v4_to_v5(&mut data).unwrap();
+        assert_eq!(data["webhooks"], json!([]));
+    }
+
+    #[test]
+    fn v5_to_v6_adds_completions() {
+        let mut data = json!({ "tasks": [] });
+        v5_to_v6(&mut data).unwrap();
+        assert_eq!(data["completions"], json!([]));
+    }
+
+    #[test]
+    fn v6_to_v7_renames_categories_everywhere() {
+        let mut data = json!({
+            "tasks": [task_value("Homework")],
+            "history": [{ "before": task_value("Exam"), "after": null }],
+            "trash": [{ "item": { "Task": task_value("Belongings") } }],
+            "calendar_feeds": [
+                { "category": "Event" },
+                { "category": null },
+            ],
+        });
+        v6_to_v7(&mut data).unwrap();
+        assert_eq!(data["tasks"][0]["category"], "宿題");
+        assert_eq!(data["history"][0]["before"]["category"], "テスト");
+        assert_eq!(data["history"][0]["after"], Value::Null);
+        assert_eq!(data["trash"][0]["item"]["Task"]["category"], "持ち物");
+        assert_eq!(data["calendar_feeds"][0]["category"], "イベント");
+        assert_eq!(data["calendar_feeds"][1]["category"], Value::Null);
+        let names = data["categories"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["イベント", "テスト", "宿題", "持ち物", "その他"]);
+    }
+
+    #[test]
+    fn v7_to_v8_expands_subjects() {
+        let mut data = json!({
+            "tasks": [],
+            "subjects": ["数学"],
+            "trash": [
+                { "item": { "Subject": "英語" } },
+                { "item": { "Task": task_value("宿題") } },
+            ],
+        });
+        v7_to_v8(&mut data).unwrap();
+        assert_eq!(data["subjects"][0]["name"], "数学");
+        assert_eq!(data["subjects"][0]["aliases"], json!([]));
+        assert_eq!(data["trash"][0]["item"]["Subject"]["name"], "英語");
+        assert_eq!(data["trash"][1]["item"]["Task"]["subject"], "数学");
+    }
+
+    #[test]
+    fn v7_to_v8_requires_subjects() {
+        let mut data = json!({ "tasks": [] });
+        assert!(v7_to_v8(&mut data).is_err());
+    }
+
+    #[test]
+    fn migrates_v0_to_current() {
+        let data = from_value(v0_fixture()).unwrap();
+        let tasks = data.tasks.lock().unwrap();
+        assert_eq!(tasks.len(), 2);
+        let task = tasks.get(TASK_ID.parse().unwrap()).unwrap();
+        assert_eq!(task.category.0, "宿題");
+        assert_eq!(task.subject, crate::Subject::Set("数学".into()));
+        assert_eq!(data.subjects.lock().unwrap().len(), 2);
+        assert_eq!(data.categories.lock().unwrap().len(), 5);
+        assert!(data.history.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn current_version_round_trips() {
+        let value = to_value(&from_value(v0_fixture()).unwrap()).unwrap();
+        assert_eq!(value["version"], VERSION);
+        assert_eq!(migrate(value.clone()).unwrap(), value);
+    }
+
+    #[test]
+    fn backup_leaves_out_secrets() {
+        let data = from_value(v0_fixture()).unwrap();
+        let endpoint =
+            WebhookEndpoint::new("https://example.com/hook".into(), UserId::new(1)).unwrap();
+        let secret = endpoint.secret.clone();
+        data.webhooks.lock().unwrap().push(endpoint);
+
+        let backup = to_backup_value(&data).unwrap();
+        assert_eq!(backup["webhooks"], json!([]));
+        assert!(!backup.to_string().contains(&secret));
+        assert!(from_value(backup).is_ok());
+        assert!(to_value(&data).unwrap().to_string().contains(&secret));
+        assert!(!format!("{:?}", data).contains(&secret));
+    }
+
+    #[test]
+    fn rejects_newer_version() {
+        let data = json!({ "version": VERSION + 1, "tasks": [] });
+        assert!(migrate(data).is_err());
+    }
+}
@@ -0,0 +1,56 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::{Context as _, Error};
+use poise::serenity_prelude::*;
+
+use crate::data::GuildData;
+
+mod json;
+pub use json::JsonStorage;
+pub mod migration;
+mod sqlite;
+pub use sqlite::SqliteStorage;
+
+pub trait Storage: Debug + Send + Sync {
+    fn save(&self, guild_id: GuildId, data: &GuildData) -> Result<(), Error>;
+
+    fn load(&self, guild_id: GuildId) -> Result<GuildData, Error>;
+
+    fn load_all(&self) -> Result<BTreeMap<GuildId, GuildData>, Error>;
+}
+
+/// 読み込みに失敗したファイルを退避する先のパスを返します。
+fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    backup.into()
+}
+
+static BACKEND: OnceLock<Box<dyn Storage>> = OnceLock::new();
+
+/// 環境変数`STORAGE_BACKEND`に応じて保存先を初期化します。
+pub fn init() -> Result<(), Error> {
+    let backend: Box<dyn Storage> = match std::env::var("STORAGE_BACKEND").as_deref() {
+        Ok("sqlite") => {
+            let path = std::env::var("SQLITE_PATH").unwrap_or(sqlite::DEFAULT_PATH.into());
+            Box::new(SqliteStorage::open(path)?)
+        }
+        Ok("json") | Err(_) => Box::new(JsonStorage),
+        Ok(other) => anyhow::bail!("Unknown storage backend: {}", other),
+    };
+    println!("Using storage backend: {:?}", backend);
+    BACKEND
+        .set(backend)
+        .ok()
+        .context("Storage already initialized")?;
+    Ok(())
+}
+
+pub fn backend() -> &'static dyn Storage {
+    BACKEND.get().expect("Storage not initialized").as_ref()
+}
@@ -0,0 +1,171 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{Context as _, Error};
+use poise::serenity_prelude::*;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::{backup_path, migration, JsonStorage, Storage};
+use crate::data::{GuildData, Task};
+
+pub const DEFAULT_PATH: &str = "data.sqlite3";
+
+// PRAGMA user_version で管理するスキーマの変更履歴
+// 日時や教科での絞り込みは読み込んだ後にメモリ上のTaskStoreで行うので、タスクの行はIDでのみ引く
+const SCHEMA: &[&str] = &["
+    CREATE TABLE guilds (
+        guild_id INTEGER PRIMARY KEY,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE tasks (
+        guild_id INTEGER NOT NULL,
+        id TEXT NOT NULL,
+        data TEXT NOT NULL,
+        PRIMARY KEY (guild_id, id)
+    );
+"];
+
+/// タスクを1件ずつ行に分けてSQLiteに保存し、保存のたびに変更のあった行だけを書き換えます。
+/// タスク以外の設定はギルドごとにJSONとしてまとめて保存します。
+#[derive(Debug)]
+pub struct SqliteStorage {
+    path: PathBuf,
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let mut connection = Connection::open(path.as_ref())?;
+        let version =
+            connection.query_row("PRAGMA user_version", [], |row| row.get::<_, usize>(0))?;
+
+        let transaction = connection.transaction()?;
+        for (i, schema) in SCHEMA.iter().enumerate().skip(version) {
+            transaction.execute_batch(schema)?;
+            transaction.pragma_update(None, "user_version", i + 1)?;
+        }
+        transaction.commit()?;
+
+        let storage = Self {
+            path: path.as_ref().to_path_buf(),
+            connection: Mutex::new(connection),
+        };
+
+        // 新しく作成したデータベースには、既存のJSONファイルを一度だけ取り込む
+        if version == 0 {
+            for (guild_id, data) in JsonStorage.load_all()? {
+                storage.save(guild_id, &data)?;
+                println!(
+                    "Migrated guild {} from JSON to {:?}",
+                    guild_id, storage.path
+                );
+            }
+        }
+
+        Ok(storage)
+    }
+}
+
+fn guild_key(guild_id: GuildId) -> i64 {
+    guild_id.get() as i64
+}
+
+impl Storage for SqliteStorage {
+    fn save(&self, guild_id: GuildId, data: &GuildData) -> Result<(), Error> {
+        let mut settings = migration::to_value(data)?;
+        let tasks = settings
+            .as_object_mut()
+            .and_then(|o| o.remove("tasks"))
+            .context("Invalid guild data")?;
+        let tasks: Vec<Task> = serde_json::from_value(tasks)?;
+
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO guilds (guild_id, data) VALUES (?1, ?2)
+             ON CONFLICT (guild_id) DO UPDATE SET data = excluded.data",
+            params![guild_key(guild_id), settings.to_string()],
+        )?;
+        {
+            let current = tasks
+                .iter()
+                .map(|task| Ok((task.id.to_string(), serde_json::to_string(task)?)))
+                .collect::<Result<BTreeMap<_, _>, Error>>()?;
+            let stored = transaction
+                .prepare("SELECT id, data FROM tasks WHERE guild_id = ?1")?
+                .query_map(params![guild_key(guild_id)], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })?
+                .collect::<Result<BTreeMap<_, _>, _>>()?;
+
+            let mut delete =
+                transaction.prepare("DELETE FROM tasks WHERE guild_id = ?1 AND id = ?2")?;
+            for id in stored.keys().filter(|id| !current.contains_key(*id)) {
+                delete.execute(params![guild_key(guild_id), id])?;
+            }
+            let mut upsert = transaction.prepare(
+                "INSERT INTO tasks (guild_id, id, data) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (guild_id, id) DO UPDATE SET data = excluded.data",
+            )?;
+            for (id, data) in current
+                .iter()
+                .filter(|(id, data)| stored.get(*id) != Some(*data))
+            {
+                upsert.execute(params![guild_key(guild_id), id, data])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn load(&self, guild_id: GuildId) -> Result<GuildData, Error> {
+        let connection = self.connection.lock().unwrap();
+        let settings = connection
+            .query_row(
+                "SELECT data FROM guilds WHERE guild_id = ?1",
+                params![guild_key(guild_id)],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?
+            .with_context(|| format!("Guild {} not found", guild_id))?;
+        let tasks = connection
+            .prepare("SELECT data FROM tasks WHERE guild_id = ?1")?
+            .query_map(params![guild_key(guild_id)], |row| row.get::<_, String>(0))?
+            .map(|data| Ok(serde_json::from_str::<serde_json::Value>(&data?)?))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let mut data: serde_json::Value = serde_json::from_str(&settings)?;
+        data.as_object_mut()
+            .context("Invalid guild data")?
+            .insert("tasks".into(), tasks.into());
+        migration::from_value(data).or_else(|e| {
+            let backup = backup_path(&self.path);
+            fs::copy(&self.path, &backup)?;
+            Err(e.context(format!(
+                "Failed to load guild {} (the original database is kept as {:?})",
+                guild_id, backup
+            )))
+        })
+    }
+
+    fn load_all(&self) -> Result<BTreeMap<GuildId, GuildData>, Error> {
+        let guild_ids = self
+            .connection
+            .lock()
+            .unwrap()
+            .prepare("SELECT guild_id FROM guilds")?
+            .query_map([], |row| row.get::<_, i64>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        guild_ids
+            .into_iter()
+            .map(|id| {
+                let guild_id = GuildId::new(id as u64);
+                Ok((guild_id, self.load(guild_id)?))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,407 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::{Bound, RangeBounds},
+};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{Category, Subject, Task};
+
+/// メモリ上でタスクを管理し、日時・教科・カテゴリーから引けるようにします。
+/// 保存するときはタスクの配列になります。
+#[derive(Debug, Default, Clone)]
+pub struct TaskStore {
+    tasks: BTreeMap<Uuid, Task>,
+    by_datetime: BTreeSet<(DateTime<Local>, Uuid)>,
+    by_subject: BTreeMap<Subject, BTreeSet<(DateTime<Local>, Uuid)>>,
+    by_category: BTreeMap<Category, BTreeSet<(DateTime<Local>, Uuid)>>,
+    recurring: BTreeSet<Uuid>,
+    // 終わりの日時があり、繰り返さないタスク
+    spanning: BTreeSet<Uuid>,
+}
+
+impl TaskStore {
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<&Task> {
+        self.tasks.get(&id)
+    }
+
+    /// タスクを追加します。同じIDのタスクがあれば置き換え、元のタスクを返します。
+    pub fn insert(&mut self, task: Task) -> Option<Task> {
+        let before = self.remove(task.id);
+        let key = (task.datetime, task.id);
+        self.by_datetime.insert(key);
+        self.by_subject
+            .entry(task.subject.clone())
+            .or_default()
+            .insert(key);
+        self.by_category
+            .entry(task.category.clone())
+            .or_default()
+            .insert(key);
+        if task.recurrence.is_some() {
+            self.recurring.insert(task.id);
+        } else if task.end.is_some() {
+            self.spanning.insert(task.id);
+        }
+        self.tasks.insert(task.id, task);
+        before
+    }
+
+    pub fn remove(&mut self, id: Uuid) -> Option<Task> {
+        let task = self.tasks.remove(&id)?;
+        let key = (task.datetime, task.id);
+        self.by_datetime.remove(&key);
+        self.recurring.remove(&id);
+        self.spanning.remove(&id);
+        if let Some(ids) = self.by_subject.get_mut(&task.subject) {
+            ids.remove(&key);
+            if ids.is_empty() {
+                self.by_subject.remove(&task.subject);
+            }
+        }
+        if let Some(ids) = self.by_category.get_mut(&task.category) {
+            ids.remove(&key);
+            if ids.is_empty() {
+                self.by_category.remove(&task.category);
+            }
+        }
+        Some(task)
+    }
+
+    /// すべてのタスクを日時順で返します。
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Task> {
+        self.by_datetime.iter().map(|(_, id)| &self.tasks[id])
+    }
+
+    /// 期間・教科・カテゴリーで絞り込み、日時順で返します。
+    /// 繰り返すタスクは期間内の各回に分けて返します。
+    /// 終わりの日時があるタスクは、期間と少しでも重なれば返します。
+    pub fn query(
+        &self,
+        range: impl RangeBounds<DateTime<Local>>,
+        subject: Option<&Subject>,
+        category: Option<&Category>,
+    ) -> Vec<Task> {
+        let bounds = (range.start_bound().cloned(), range.end_bound().cloned());
+        let matches = |task: &Task| {
+            subject.is_none_or(|s| &task.subject == s)
+                && category.is_none_or(|c| &task.category == c)
+        };
+
+        let index = match (subject, category) {
+            (None, None) => Some(&self.by_datetime),
+            (Some(subject), _) => self.by_subject.get(subject),
+            (None, Some(category)) => self.by_category.get(category),
+        };
+        let mut tasks = key_range(bounds)
+            .into_iter()
+            .flat_map(|keys| index.into_iter().flat_map(move |index| index.range(keys)))
+            .map(|(_, id)| &self.tasks[id])
+            .filter(|task| task.recurrence.is_none() && task.end.is_none() && matches(task))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        // 繰り返すタスクは最初の回の日時で、期間のあるタスクは始まりの日時で索引されているので、別に調べる
+        tasks.extend(
+            self.recurring
+                .iter()
+                .chain(&self.spanning)
+                .map(|id| &self.tasks[id])
+                .filter(|task| matches(task))
+                .flat_map(|task| {
+                    task.occurrences()
+                        .take_while(|t| !is_after(bounds.1, t.datetime))
+                        .filter(|t| !is_before(bounds.0, t.end.unwrap_or(t.datetime)))
+                }),
+        );
+        tasks.sort_by_key(|task| task.datetime);
+        tasks
+    }
+
+    pub fn retain(&mut self, mut f: impl FnMut(&Task) -> bool) {
+        let removed = self
+            .tasks
+            .values()
+            .filter(|task| !f(task))
+            .map(|task| task.id)
+            .collect::<Vec<_>>();
+        for id in removed {
+            self.remove(id);
+        }
+    }
+}
+
+type KeyRange = (
+    Bound<(DateTime<Local>, Uuid)>,
+    Bound<(DateTime<Local>, Uuid)>,
+);
+
+// 日時が範囲の終わりより後かどうか
+fn is_after(end: Bound<DateTime<Local>>, datetime: DateTime<Local>) -> bool {
+    match end {
+        Bound::Included(end) => datetime > end,
+        Bound::Excluded(end) => datetime >= end,
+        Bound::Unbounded => false,
+    }
+}
+
+// 日時が範囲の始まりより前かどうか
+fn is_before(start: Bound<DateTime<Local>>, datetime: DateTime<Local>) -> bool {
+    match start {
+        Bound::Included(start) => datetime < start,
+        Bound::Excluded(start) => datetime <= start,
+        Bound::Unbounded => false,
+    }
+}
+
+// 同じ日時のタスクもすべて含まれるように、IDの最小値と最大値で範囲を作る
+// 空の範囲ではBTreeSet::rangeがパニックするため、Noneを返す
+fn key_range(range: impl RangeBounds<DateTime<Local>>) -> Option<KeyRange> {
+    let start = match range.start_bound() {
+        Bound::Included(datetime) => Bound::Included((*datetime, Uuid::nil())),
+        Bound::Excluded(datetime) => Bound::Excluded((*datetime, Uuid::max())),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(datetime) => Bound::Included((*datetime, Uuid::max())),
+        Bound::Excluded(datetime) => Bound::Excluded((*datetime, Uuid::nil())),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    let valid = match (&start, &end) {
+        (Bound::Included(s), Bound::Included(e)) => s <= e,
+        (Bound::Included(s) | Bound::Excluded(s), Bound::Included(e) | Bound::Excluded(e)) => s < e,
+        _ => true,
+    };
+    valid.then_some((start, end))
+}
+
+impl FromIterator<Task> for TaskStore {
+    fn from_iter<I: IntoIterator<Item = Task>>(iter: I) -> Self {
+        let mut store = TaskStore::default();
+        store.extend(iter);
+        store
+    }
+}
+
+impl Extend<Task> for TaskStore {
+    fn extend<I: IntoIterator<Item = Task>>(&mut self, iter: I) {
+        for task in iter {
+            self.insert(task);
+        }
+    }
+}
+
+impl Serialize for TaskStore {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::ser::Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for TaskStore {
+    fn deserialize<D>(deserializer: D) -> Result<TaskStore, D::Error>
+    where
+        D: serde::de::Deserializer<'de>,
+    {
+        Ok(Vec::<Task>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::{
+        fixtures::{datetime, task},
+        recurrence::{Recurrence, RecurrenceEnd, RecurrenceRule},
+    };
+
+    fn at(day: u32, hour: u32) -> DateTime<Local> {
+        datetime(2024, 10, day, hour, 0)
+    }
+
+    fn store() -> TaskStore {
+        [
+            Task {
+                datetime: at(11, 9),
+                ..task("ワーク")
+            },
+            Task {
+                category: Category("テスト".into()),
+                datetime: at(15, 9),
+                ..task("小テスト")
+            },
+            Task {
+                category: Category("持ち物".into()),
+                subject: Subject::Unset,
+                datetime: at(20, 9),
+                ..task("持ち物")
+            },
+            Task {
+                subject: Subject::Set("英語".into()),
+                datetime: at(14, 9),
+                recurrence: Some(Recurrence {
+                    rule: RecurrenceRule::Daily(7),
+                    end: RecurrenceEnd::Count(3),
+                    exceptions: Default::default(),
+                }),
+                ..task("週次")
+            },
+            Task {
+                category: Category("イベント".into()),
+                subject: Subject::Unset,
+                datetime: at(10, 9),
+                end: Some(at(12, 17)),
+                ..task("合宿")
+            },
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    fn details(tasks: Vec<Task>) -> Vec<String> {
+        tasks.into_iter().map(|task| task.details).collect()
+    }
+
+    #[test]
+    fn queries_in_datetime_order() {
+        let store = store();
+        assert_eq!(
+            details(store.query(.., None, None)),
+            [
+                "合宿",
+                "ワーク",
+                "週次",
+                "小テスト",
+                "持ち物",
+                "週次",
+                "週次"
+            ]
+        );
+        assert_eq!(
+            details(store.query(at(13, 0)..at(16, 0), None, None)),
+            ["週次", "小テスト"]
+        );
+    }
+
+    #[test]
+    fn range_bounds() {
+        let store = store();
+        assert_eq!(
+            details(store.query(at(15, 9)..=at(15, 9), None, None)),
+            ["小テスト"]
+        );
+        assert!(store.query(at(15, 9)..at(15, 9), None, None).is_empty());
+        assert!(store.query(at(16, 0)..at(15, 0), None, None).is_empty());
+        assert_eq!(
+            details(store.query(at(20, 9).., None, None)),
+            ["持ち物", "週次", "週次"]
+        );
+    }
+
+    #[test]
+    fn filters_by_subject_and_category() {
+        let store = store();
+        let math = Subject::Set("数学".into());
+        let homework = Category("宿題".into());
+        assert_eq!(
+            details(store.query(.., Some(&math), None)),
+            ["ワーク", "小テスト"]
+        );
+        assert_eq!(
+            details(store.query(.., None, Some(&homework))),
+            ["ワーク", "週次", "週次", "週次"]
+        );
+        assert_eq!(
+            details(store.query(.., Some(&math), Some(&homework))),
+            ["ワーク"]
+        );
+        assert_eq!(
+            details(store.query(.., Some(&Subject::Unset), None)),
+            ["合宿", "持ち物"]
+        );
+        assert!(store
+            .query(.., Some(&Subject::Set("理科".into())), None)
+            .is_empty());
+    }
+
+    #[test]
+    fn expands_recurring_tasks() {
+        let store = store();
+        let english = Subject::Set("英語".into());
+        let dates = store
+            .query(.., Some(&english), None)
+            .into_iter()
+            .map(|task| task.datetime)
+            .collect::<Vec<_>>();
+        assert_eq!(dates, [at(14, 9), at(21, 9), at(28, 9)]);
+        assert_eq!(
+            store.query(at(22, 0).., Some(&english), None)[0].datetime,
+            at(28, 9)
+        );
+    }
+
+    #[test]
+    fn includes_overlapping_spanning_tasks() {
+        let store = store();
+        let event = Category("イベント".into());
+        // 始まりが期間より前でも、終わりが期間に入っていれば含める
+        assert_eq!(
+            details(store.query(at(12, 0)..at(13, 0), None, Some(&event))),
+            ["合宿"]
+        );
+        assert!(store.query(at(12, 18).., None, Some(&event)).is_empty());
+        assert!(store.query(..at(10, 9), None, Some(&event)).is_empty());
+    }
+
+    #[test]
+    fn insert_replaces_and_remove_updates_indexes() {
+        let mut store = store();
+        let mut task = store.query(.., Some(&Subject::Set("数学".into())), None)[0].clone();
+        task.subject = Subject::Set("理科".into());
+        task.datetime += Duration::days(10);
+        assert!(store.insert(task.clone()).is_some());
+        assert_eq!(store.len(), 5);
+        assert_eq!(
+            details(store.query(.., Some(&Subject::Set("数学".into())), None)),
+            ["小テスト"]
+        );
+        assert_eq!(
+            store.query(.., Some(&Subject::Set("理科".into())), None)[0].datetime,
+            at(21, 9)
+        );
+
+        store.retain(|t| t.id != task.id);
+        assert!(store.get(task.id).is_none());
+        assert!(store
+            .query(.., Some(&Subject::Set("理科".into())), None)
+            .is_empty());
+    }
+
+    #[test]
+    fn serializes_as_array() {
+        let store = store();
+        let value = serde_json::to_value(&store).unwrap();
+        assert_eq!(value.as_array().unwrap().len(), 5);
+        let restored: TaskStore = serde_json::from_value(value).unwrap();
+        assert_eq!(
+            details(restored.query(.., None, None)),
+            details(store.query(.., None, None))
+        );
+    }
+}
@@ -0,0 +1,32 @@
+use crate::{data, PoiseContext};
+
+pub async fn autocomplete_subject(ctx: PoiseContext<'_>, partial: &str) -> Vec<String> {
+    let Ok((_, guild_data)) = data::of(ctx) else {
+        return vec![];
+    };
+    let subjects = guild_data.subjects.lock().unwrap().clone();
+    subjects
+        .into_iter()
+        .filter(|s| {
+            std::iter::once(&s.name)
+                .chain(&s.short_code)
+                .chain(&s.aliases)
+                .any(|n| n.contains(partial))
+        })
+        .map(|s| s.name)
+        .take(25)
+        .collect()
+}
+
+pub async fn autocomplete_category(ctx: PoiseContext<'_>, partial: &str) -> Vec<String> {
+    let Ok((_, guild_data)) = data::of(ctx) else {
+        return vec![];
+    };
+    let categories = guild_data.categories.lock().unwrap().clone();
+    categories
+        .iter()
+        .map(|c| c.name.clone())
+        .filter(|c| c.contains(partial))
+        .take(25)
+        .collect()
+}
@@ -1,4 +1,14 @@
+mod autocomplete;
+pub use autocomplete::{autocomplete_category, autocomplete_subject};
 mod format_date;
 pub use format_date::format_date;
 mod format_datetime;
 pub use format_datetime::format_datetime;
+mod parse_color;
+pub use parse_color::parse_color;
+mod parse_date;
+pub use parse_date::parse_date;
+mod parse_emoji;
+pub use parse_emoji::parse_emoji;
+mod truncate;
+pub use truncate::truncate;
@@ -0,0 +1,8 @@
+use anyhow::{Context as _, Error};
+
+/// 「#ff8800」のような色を読み取ります。
+pub fn parse_color(text: &str) -> Result<u32, Error> {
+    let hex = text.trim().trim_start_matches('#');
+    anyhow::ensure!(hex.len() == 6, "色は#ff8800のように指定してください");
+    u32::from_str_radix(hex, 16).with_context(|| format!("色を読み取れませんでした: {}", text))
+}
@@ -0,0 +1,9 @@
+use anyhow::{Context as _, Error};
+use chrono::NaiveDate;
+
+pub fn parse_date(text: &str) -> Result<NaiveDate, Error> {
+    ["%Y/%m/%d", "%Y-%m-%d"]
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(text.trim(), format).ok())
+        .with_context(|| format!("日付を読み取れませんでした: {}", text))
+}
@@ -0,0 +1,86 @@
+use anyhow::Error;
+use poise::serenity_prelude::ReactionType;
+
+/// 絵文字として使えるか確かめて、前後の空白を除いた文字列を返します。
+/// `<:name:id>`・`<a:name:id>`の形式のカスタム絵文字か、1つのUnicodeの絵文字のみ受け付けます。
+pub fn parse_emoji(text: &str) -> Result<String, Error> {
+    let text = text.trim();
+    let valid = match text.parse::<ReactionType>() {
+        Ok(ReactionType::Custom { .. }) => true,
+        Ok(_) => is_unicode_emoji(text),
+        Err(_) => false,
+    };
+    anyhow::ensure!(valid, "絵文字を読み取れませんでした: {}", text);
+    Ok(text.to_string())
+}
+
+// 選択肢の絵文字としてDiscordに拒否されないよう、1つの絵文字になっているかを確かめる
+fn is_unicode_emoji(text: &str) -> bool {
+    let chars = text.chars().collect::<Vec<_>>();
+    match chars.as_slice() {
+        [] => false,
+        // キーキャップ(1️⃣など)
+        [base, '\u{FE0F}', '\u{20E3}'] | [base, '\u{20E3}'] => {
+            base.is_ascii_digit() || matches!(base, '#' | '*')
+        }
+        // 国旗
+        [a, b] if is_regional_indicator(*a) && is_regional_indicator(*b) => true,
+        // ゼロ幅接合子でつないだ絵文字(👨‍👩‍👧など)は、それぞれの部分が絵文字であればよい
+        _ => text.split('\u{200D}').all(|part| {
+            let mut chars = part.chars();
+            chars.next().is_some_and(is_emoji_base) && chars.all(is_emoji_modifier)
+        }),
+    }
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&c)
+}
+
+fn is_emoji_base(c: char) -> bool {
+    matches!(c,
+        '\u{00A9}' | '\u{00AE}' | '\u{203C}' | '\u{2049}' | '\u{2122}' | '\u{2139}'
+        | '\u{2194}'..='\u{21FF}'
+        | '\u{2300}'..='\u{23FF}'
+        | '\u{24C2}'
+        | '\u{25A0}'..='\u{27BF}'
+        | '\u{2934}' | '\u{2935}'
+        | '\u{2B00}'..='\u{2BFF}'
+        | '\u{3030}' | '\u{303D}' | '\u{3297}' | '\u{3299}'
+        | '\u{1F000}'..='\u{1FAFF}')
+        && !is_regional_indicator(c)
+}
+
+// 異体字セレクター・肌の色・タグ
+fn is_emoji_modifier(c: char) -> bool {
+    matches!(c,
+        '\u{FE0E}' | '\u{FE0F}' | '\u{20E3}'
+        | '\u{1F3FB}'..='\u{1F3FF}'
+        | '\u{E0020}'..='\u{E007F}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_unicode_emoji() {
+        for emoji in ["📘", "✏️", "1️⃣", "🇯🇵", "👍🏽", "👨‍👩‍👧", " 🧪 "]
+        {
+            assert!(parse_emoji(emoji).is_ok(), "{}", emoji);
+        }
+    }
+
+    #[test]
+    fn accepts_custom_emoji() {
+        assert!(parse_emoji("<:math:123456789012345678>").is_ok());
+        assert!(parse_emoji("<a:spin:123456789012345678>").is_ok());
+    }
+
+    #[test]
+    fn rejects_text() {
+        for text in ["", "abc", "数学", "📘📗", "1", "<:broken>", "📘a"] {
+            assert!(parse_emoji(text).is_err(), "{}", text);
+        }
+    }
+}
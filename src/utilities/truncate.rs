@@ -0,0 +1,30 @@
+/// 文字数の上限を超える場合は切り詰め、最後の1文字を「…」にします。
+/// 選択肢(100文字)や埋め込みのフィールド名(256文字)など、Discordの文字数制限に合わせるために使います。
+pub fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut truncated = text
+        .chars()
+        .take(max_chars.saturating_sub(1))
+        .collect::<String>();
+    truncated.push('…');
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_short_text() {
+        assert_eq!(truncate("数学 ワーク", 6), "数学 ワーク");
+    }
+
+    #[test]
+    fn truncates_by_chars() {
+        let truncated = truncate(&"あ".repeat(150), 100);
+        assert_eq!(truncated.chars().count(), 100);
+        assert!(truncated.ends_with("あ…"));
+    }
+}
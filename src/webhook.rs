@@ -0,0 +1,169 @@
+use std::time::Duration;
+
+use anyhow::{Context as _, Error};
+use chrono::{DateTime, Local};
+use hmac::{Hmac, Mac};
+use poise::serenity_prelude::UserId;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::{data::TaskEvent, Task};
+
+// 最初の送信を含めた試行回数
+const MAX_ATTEMPTS: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// タスクが変更されたときに通知する送信先
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct WebhookEndpoint {
+    pub id: Uuid,
+    pub url: String,
+    // 署名に使う鍵
+    pub secret: String,
+    pub created_by: UserId,
+    pub created_at: DateTime<Local>,
+}
+
+// 起動時に表示する設定などに鍵が出ないよう、Debugでは伏せる
+impl std::fmt::Debug for WebhookEndpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebhookEndpoint")
+            .field("id", &self.id)
+            .field("url", &self.url)
+            .field("secret", &"<redacted>")
+            .field("created_by", &self.created_by)
+            .field("created_at", &self.created_at)
+            .finish()
+    }
+}
+
+impl WebhookEndpoint {
+    pub fn new(url: String, created_by: UserId) -> Result<Self, Error> {
+        let parsed = reqwest::Url::parse(&url).context("URLを読み取れませんでした")?;
+        anyhow::ensure!(
+            matches!(parsed.scheme(), "http" | "https"),
+            "http または https のURLを指定してください"
+        );
+        Ok(Self {
+            id: Uuid::new_v4(),
+            url,
+            secret: Uuid::new_v4().simple().to_string(),
+            created_by,
+            created_at: Local::now(),
+        })
+    }
+}
+
+/// 送信するJSONの内容
+#[derive(Serialize, Debug)]
+pub struct Payload {
+    // task.created / task.updated / task.deleted / ping
+    pub event: &'static str,
+    pub id: Uuid,
+    pub timestamp: DateTime<Local>,
+    pub actor: UserId,
+    pub before: Option<Task>,
+    pub after: Option<Task>,
+    pub reverts: Option<Uuid>,
+}
+
+impl From<&TaskEvent> for Payload {
+    fn from(event: &TaskEvent) -> Self {
+        Self {
+            event: match (&event.before, &event.after) {
+                (None, _) => "task.created",
+                (Some(_), Some(_)) => "task.updated",
+                (Some(_), None) => "task.deleted",
+            },
+            id: event.id,
+            timestamp: event.timestamp,
+            actor: event.actor,
+            before: event.before.clone(),
+            after: event.after.clone(),
+            reverts: event.reverts,
+        }
+    }
+}
+
+impl Payload {
+    /// 送信先の確認に使う、タスクを含まない通知
+    pub fn ping(actor: UserId) -> Self {
+        Self {
+            event: "ping",
+            id: Uuid::new_v4(),
+            timestamp: Local::now(),
+            actor,
+            before: None,
+            after: None,
+            reverts: None,
+        }
+    }
+}
+
+/// 登録されたすべての送信先に、バックグラウンドで通知します。
+pub fn dispatch(endpoints: Vec<WebhookEndpoint>, payload: Payload) {
+    if endpoints.is_empty() {
+        return;
+    }
+    let body = match serde_json::to_vec(&payload) {
+        Ok(body) => body,
+        Err(e) => {
+            eprintln!("Failed to serialize webhook payload: {:?}", e);
+            return;
+        }
+    };
+    for endpoint in endpoints {
+        let body = body.clone();
+        tokio::spawn(async move {
+            if let Err(e) = deliver(&endpoint, &body).await {
+                eprintln!("Failed to deliver webhook to {}: {:?}", endpoint.url, e);
+            }
+        });
+    }
+}
+
+/// 失敗した場合は、間隔を倍にしながら再送します。
+pub async fn deliver(endpoint: &WebhookEndpoint, body: &[u8]) -> Result<(), Error> {
+    let client = reqwest::Client::builder().timeout(TIMEOUT).build()?;
+    let signature = sign(&endpoint.secret, body);
+
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        let result = client
+            .post(&endpoint.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-Webhook-Signature", format!("sha256={}", signature))
+            .body(body.to_vec())
+            .send()
+            .await;
+        let error = match result {
+            Ok(response) if response.status().is_success() => return Ok(()),
+            Ok(response) => {
+                let status = response.status();
+                // 送信先の設定の誤りなどは、再送しても成功しない
+                if !(status.is_server_error() || status.as_u16() == 429) {
+                    anyhow::bail!("Endpoint responded with {}", status);
+                }
+                anyhow::anyhow!("Endpoint responded with {}", status)
+            }
+            Err(e) => e.into(),
+        };
+        if attempt >= MAX_ATTEMPTS {
+            return Err(error.context(format!("Gave up after {} attempts", attempt)));
+        }
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+        attempt += 1;
+    }
+}
+
+// 受信側は同じ鍵でHMAC-SHA256を計算して、送信元を確認できる
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}